@@ -0,0 +1,300 @@
+use clap::ValueEnum;
+use rodio::{OutputStream, Sink, Source};
+use serde::Deserialize;
+use std::f32::consts::TAU;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const SAMPLE_RATE: u32 = 44_100;
+// 44100 / 60 is exactly 735, so the audio never drifts from the video
+pub const SAMPLES_PER_FRAME: u32 = SAMPLE_RATE / 60;
+// The tone fades in and out over 5 ms, short enough to stay on the frame where ST
+// changes and long enough that starting and stopping it doesn't click
+const ENVELOPE_SAMPLES: f32 = SAMPLE_RATE as f32 * 0.005;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Waveform {
+    // What the VIP's buzzer puts out
+    #[default]
+    Square,
+    Sine,
+    Triangle,
+}
+
+impl Waveform {
+    // From -1 to 1, `phase` going from 0 to 1 over one period
+    fn at(self, phase: f32) -> f32 {
+        match self {
+            Self::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Self::Sine => (phase * TAU).sin(),
+            Self::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tone {
+    pub frequency: f32,
+    pub waveform: Waveform,
+    // From 0 to 1
+    pub volume: f32,
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Self {
+            frequency: 440.0,
+            waveform: Waveform::default(),
+            volume: 0.25,
+        }
+    }
+}
+
+// The buzzer one sample at a time, `gate` says whether it is on
+struct Oscillator {
+    tone: Tone,
+    phase: f32,
+    level: f32,
+}
+
+impl Oscillator {
+    fn new(tone: Tone) -> Self {
+        Self {
+            tone,
+            phase: 0.0,
+            level: 0.0,
+        }
+    }
+    fn sample(&mut self, gate: bool) -> f32 {
+        self.level = if gate {
+            (self.level + 1.0 / ENVELOPE_SAMPLES).min(1.0)
+        } else {
+            (self.level - 1.0 / ENVELOPE_SAMPLES).max(0.0)
+        };
+        let sample = self.tone.waveform.at(self.phase) * self.tone.volume * self.level;
+        self.phase = (self.phase + self.tone.frequency / SAMPLE_RATE as f32).fract();
+        sample
+    }
+}
+
+// Where the buzzer goes, picked by the frontend. The CPU only says whether it sounds
+// during each emulated frame, so sinks never look at ST or at the wall clock
+pub trait AudioSink {
+    // Called once per emulated frame, after the timers tick
+    fn frame(&mut self, buzzer_on: bool) -> Result<(), Box<dyn std::error::Error + 'static>>;
+    // Goes quiet until the next frame says otherwise, while paused
+    fn pause(&mut self) {}
+    // Only sinks that can be heard mute, files keep the buzzer
+    fn set_muted(&mut self, _muted: bool) {}
+    // Once the emulator stops, for sinks that have something left to write
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error + 'static>> {
+        Ok(())
+    }
+}
+
+// Several sinks at once, like the audio device and a WAV file
+pub struct CombinedAudio {
+    sinks: Vec<Box<dyn AudioSink>>,
+}
+
+impl CombinedAudio {
+    pub fn new(sinks: Vec<Box<dyn AudioSink>>) -> Self {
+        Self { sinks }
+    }
+}
+
+impl AudioSink for CombinedAudio {
+    fn frame(&mut self, buzzer_on: bool) -> Result<(), Box<dyn std::error::Error + 'static>> {
+        self.sinks
+            .iter_mut()
+            .try_for_each(|sink| sink.frame(buzzer_on))
+    }
+    fn pause(&mut self) {
+        self.sinks.iter_mut().for_each(|sink| sink.pause());
+    }
+    fn set_muted(&mut self, muted: bool) {
+        self.sinks.iter_mut().for_each(|sink| sink.set_muted(muted));
+    }
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error + 'static>> {
+        self.sinks.iter_mut().try_for_each(|sink| sink.finish())
+    }
+}
+
+// No sound at all, for --no-audio, headless runs, tests and machines without an audio device
+pub struct NullAudio;
+
+impl AudioSink for NullAudio {
+    fn frame(&mut self, _buzzer_on: bool) -> Result<(), Box<dyn std::error::Error + 'static>> {
+        Ok(())
+    }
+}
+
+// Plays forever on the audio thread, following the gate the emulator sets every frame
+struct BuzzerSource {
+    oscillator: Oscillator,
+    gate: Arc<AtomicBool>,
+}
+
+impl Iterator for BuzzerSource {
+    type Item = f32;
+    fn next(&mut self) -> Option<f32> {
+        Some(self.oscillator.sample(self.gate.load(Ordering::Relaxed)))
+    }
+}
+
+impl Source for BuzzerSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+    fn channels(&self) -> u16 {
+        1
+    }
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+// The default audio device
+pub struct RodioAudio {
+    _stream: OutputStream,
+    _sink: Sink,
+    gate: Arc<AtomicBool>,
+    muted: bool,
+}
+
+impl RodioAudio {
+    pub fn new(tone: Tone) -> Result<Self, Box<dyn std::error::Error + 'static>> {
+        let (stream, handle) = OutputStream::try_default()?;
+        let sink = Sink::try_new(&handle)?;
+        let gate = Arc::new(AtomicBool::new(false));
+        sink.append(BuzzerSource {
+            oscillator: Oscillator::new(tone),
+            gate: gate.clone(),
+        });
+        Ok(Self {
+            _stream: stream,
+            _sink: sink,
+            gate,
+            muted: false,
+        })
+    }
+}
+
+impl AudioSink for RodioAudio {
+    fn frame(&mut self, buzzer_on: bool) -> Result<(), Box<dyn std::error::Error + 'static>> {
+        self.gate.store(buzzer_on && !self.muted, Ordering::Relaxed);
+        Ok(())
+    }
+    fn pause(&mut self) {
+        self.gate.store(false, Ordering::Relaxed);
+    }
+    fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        if muted {
+            self.pause();
+        }
+    }
+}
+
+// The buzzer in a 16 bit mono WAV file, 735 samples for every emulated frame however
+// long it took, so the file is sample accurate even when running headless or paused
+pub struct WavAudio {
+    writer: Option<hound::WavWriter<BufWriter<File>>>,
+    oscillator: Oscillator,
+}
+
+impl WavAudio {
+    pub fn create(path: &Path, tone: Tone) -> Result<Self, Box<dyn std::error::Error + 'static>> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let writer =
+            hound::WavWriter::create(path, spec).map_err(|e| format!("{e}: {}", path.display()))?;
+        Ok(Self {
+            writer: Some(writer),
+            oscillator: Oscillator::new(tone),
+        })
+    }
+}
+
+impl AudioSink for WavAudio {
+    fn frame(&mut self, buzzer_on: bool) -> Result<(), Box<dyn std::error::Error + 'static>> {
+        let Some(writer) = &mut self.writer else {
+            return Err("The WAV file was already finished".into());
+        };
+        for _ in 0..SAMPLES_PER_FRAME {
+            let sample = self.oscillator.sample(buzzer_on) * i16::MAX as f32;
+            writer.write_sample(sample as i16)?;
+        }
+        Ok(())
+    }
+    // Writes the header with the final length, dropping the sink does the same ignoring errors
+    fn finish(&mut self) -> Result<(), Box<dyn std::error::Error + 'static>> {
+        if let Some(writer) = self.writer.take() {
+            writer.finalize()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    #[test]
+    fn should_shape_each_waveform() {
+        assert_eq!(Waveform::Square.at(0.25), 1.0);
+        assert_eq!(Waveform::Square.at(0.75), -1.0);
+        assert!((Waveform::Sine.at(0.25) - 1.0).abs() < 1e-6);
+        assert_eq!(Waveform::Triangle.at(0.0), -1.0);
+        assert_eq!(Waveform::Triangle.at(0.5), 1.0);
+        assert_eq!(Waveform::Triangle.at(0.75), 0.0);
+    }
+    #[test]
+    fn should_fade_the_buzzer_in_and_out_on_its_frames() {
+        let path = env::temp_dir().join("chip-8-emulator-audio-test.wav");
+        let tone = Tone {
+            volume: 0.5,
+            ..Tone::default()
+        };
+        let mut audio = WavAudio::create(&path, tone).unwrap();
+        audio.frame(false).unwrap();
+        audio.frame(true).unwrap();
+        audio.frame(false).unwrap();
+        audio.finish().unwrap();
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        let samples: Vec<i16> = reader.samples().map(Result::unwrap).collect();
+        let frame = SAMPLES_PER_FRAME as usize;
+        let envelope = ENVELOPE_SAMPLES as usize;
+        let full = (0.5 * i16::MAX as f32) as i16;
+        assert_eq!(samples.len(), 3 * frame);
+        assert!(samples[..frame].iter().all(|sample| *sample == 0));
+        assert!(samples[frame] > 0 && samples[frame] < full / 100);
+        assert!(samples[frame + envelope..2 * frame]
+            .iter()
+            .all(|sample| sample.abs() == full));
+        assert!(samples[2 * frame] != 0);
+        assert!(samples[2 * frame + envelope..]
+            .iter()
+            .all(|sample| *sample == 0));
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,201 @@
+use crate::audio::{Tone, Waveform, SAMPLE_RATE};
+use crate::controller::ControllerConfig;
+use crate::keymap::KeypadConfig;
+use crate::palette::{Colour, Palette};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+// Every section is optional, anything missing falls back to the CLI defaults
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub palette: PaletteConfig,
+    pub audio: AudioConfig,
+    // Action name to key name, see hotkeys.rs
+    pub hotkeys: BTreeMap<String, String>,
+    pub keypad: KeypadConfig,
+    pub controller: ControllerConfig,
+    // Settings for a single ROM, keyed by the SHA-1 of the ROM
+    pub rom: BTreeMap<String, RomConfig>,
+    // Local files in the format of the community CHIP-8 database, see database.rs
+    pub databases: Vec<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RomConfig {
+    pub keypad: KeypadConfig,
+    pub controller: ControllerConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaletteConfig {
+    pub preset: Option<String>,
+    pub foreground: Option<String>,
+    pub background: Option<String>,
+    // The colours of the second plane and of both planes, once there are two
+    pub second_plane: Option<String>,
+    pub both_planes: Option<String>,
+}
+
+impl PaletteConfig {
+    // Every colour of the palette, ghosting fades between the background and the
+    // foreground. The OSD isn't part of it, white on a black shadow reads over any palette
+    pub fn palette(&self) -> Result<Palette, String> {
+        let mut palette = match &self.preset {
+            Some(preset) => Palette::from_str(preset)?,
+            None => Palette::default(),
+        };
+        let colours = [
+            &self.background,
+            &self.foreground,
+            &self.second_plane,
+            &self.both_planes,
+        ];
+        for (colour, config) in palette.colours.iter_mut().zip(colours) {
+            if let Some(config) = config {
+                *colour = Colour::from_str(config)?;
+                palette.name = "custom".to_string();
+            }
+        }
+        Ok(palette)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioConfig {
+    pub frequency: Option<f32>,
+    pub waveform: Option<Waveform>,
+    pub volume: Option<f32>,
+}
+
+impl AudioConfig {
+    // Anything missing keeps the default tone
+    pub fn tone(&self) -> Result<Tone, String> {
+        let default = Tone::default();
+        let tone = Tone {
+            frequency: self.frequency.unwrap_or(default.frequency),
+            waveform: self.waveform.unwrap_or(default.waveform),
+            volume: self.volume.unwrap_or(default.volume),
+        };
+        let nyquist = (SAMPLE_RATE / 2) as f32;
+        if !(tone.frequency > 0.0 && tone.frequency < nyquist) {
+            return Err(format!(
+                "Buzzer frequency must be between 0 and {nyquist} Hz: {}",
+                tone.frequency
+            ));
+        }
+        if !(0.0..=1.0).contains(&tone.volume) {
+            return Err(format!("Volume must be between 0 and 1: {}", tone.volume));
+        }
+        Ok(tone)
+    }
+}
+
+impl Config {
+    pub fn rom(&self, sha1: &str) -> Option<&RomConfig> {
+        self.rom
+            .iter()
+            .find(|(hash, _)| hash.eq_ignore_ascii_case(sha1))
+            .map(|(_, rom)| rom)
+    }
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("chip8").join("config.toml"))
+    }
+    // A missing file at the default location is not an error, a missing file passed explicitly is
+    pub fn load(path: Option<&str>) -> Result<Self, Box<dyn std::error::Error + 'static>> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => match Self::default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+        let contents = fs::read_to_string(&path).map_err(|e| format!("{e}: {}", path.display()))?;
+        let config =
+            toml::from_str(contents.as_str()).map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn should_parse_palette_section() {
+        let config: Config = toml::from_str(
+            r##"
+            [palette]
+            preset = "amber"
+            foreground = "#FFFFFF"
+            "##,
+        )
+        .unwrap();
+        assert_eq!(config.palette.preset.as_deref(), Some("amber"));
+        assert_eq!(config.palette.foreground.as_deref(), Some("#FFFFFF"));
+        assert_eq!(config.palette.background, None);
+        let palette = config.palette.palette().unwrap();
+        assert_eq!(palette.foreground(), Colour::from_rgb(0xFFFFFF));
+        assert_eq!(
+            palette.background(),
+            Palette::preset("amber").unwrap().background()
+        );
+        let config: Config = toml::from_str("[palette]\nboth_planes = \"#FF0000\"").unwrap();
+        let palette = config.palette.palette().unwrap();
+        assert_eq!(palette.colours[3], Colour::from_rgb(0xFF0000));
+        assert_eq!(palette.name, "custom");
+    }
+    #[test]
+    fn should_parse_audio_section() {
+        let config: Config =
+            toml::from_str("[audio]\nwaveform = \"triangle\"\nvolume = 0.5").unwrap();
+        let tone = config.audio.tone().unwrap();
+        assert_eq!(tone.waveform, Waveform::Triangle);
+        assert_eq!(tone.volume, 0.5);
+        assert_eq!(tone.frequency, Tone::default().frequency);
+        let config: Config = toml::from_str("[audio]\nfrequency = 30000").unwrap();
+        assert!(config.audio.tone().is_err());
+        assert!(toml::from_str::<Config>("[audio]\nwaveform = \"sawtooth\"").is_err());
+    }
+    #[test]
+    fn should_parse_hotkeys_section() {
+        let config: Config = toml::from_str("[hotkeys]\npause = \"Space\"").unwrap();
+        assert_eq!(
+            config.hotkeys.get("pause").map(String::as_str),
+            Some("Space")
+        );
+    }
+    #[test]
+    fn should_parse_keypad_and_rom_overrides() {
+        let config: Config = toml::from_str(
+            r#"
+            [keypad]
+            5 = ["S", "Down"]
+            [controller]
+            threshold = 0.3
+            [rom.A9993E364706816ABA3E25717850C26C9CD0D89D.keypad]
+            5 = ["Space"]
+            [rom.A9993E364706816ABA3E25717850C26C9CD0D89D.controller.buttons]
+            5 = ["x"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.keypad["5"], ["S", "Down"]);
+        let rom = config
+            .rom("a9993e364706816aba3e25717850c26c9cd0d89d")
+            .unwrap();
+        assert_eq!(rom.keypad["5"], ["Space"]);
+        assert_eq!(config.controller.threshold, Some(0.3));
+        assert_eq!(rom.controller.buttons["5"], ["x"]);
+        assert!(config.rom("0000").is_none());
+    }
+    #[test]
+    fn should_reject_unknown_keys() {
+        assert!(toml::from_str::<Config>("[palette]\nforegroud = \"#FFFFFF\"").is_err());
+    }
+}
@@ -0,0 +1,206 @@
+// Runs the ROMs in roms/tests headlessly under every quirks profile and checks the
+// screens they are documented to end on. Only the logo is compared with a picture, the other
+// ROMs draw a tick or a cross for each check, or hex digits, which are checked glyph by glyph
+// against what the test expects rather than against what the emulator drew before.
+use crate::audio::{AudioSink, NullAudio, Tone, WavAudio, SAMPLES_PER_FRAME};
+use crate::cpu::CPU;
+use crate::display::{HeadlessDisplay, HEIGHT, WIDTH};
+use crate::font::Font;
+use crate::input::{FrontendEvent, InputSource};
+use crate::keyboard::Keyboard;
+use crate::memory::Memory;
+use crate::palette::Palette;
+use crate::quirks::{Quirks, QuirksProfile};
+use std::env;
+use std::fs;
+
+// What the ROMs draw for a check that passes and for one that fails
+const TICK: [u8; 5] = [0x02, 0x04, 0x88, 0x50, 0x20];
+const CROSS: [u8; 5] = [0x88, 0x50, 0x20, 0x50, 0x88];
+
+const PROFILES: [QuirksProfile; 3] = [
+    QuirksProfile::Cowgod,
+    QuirksProfile::Vip,
+    QuirksProfile::Schip,
+];
+
+// A key held down from the first frame up to, but not including, the second one
+struct KeyHold(u64, u64, u8);
+
+// The input source for tests, holds keys on the frames they say
+struct KeyHolds<'a>(&'a [KeyHold]);
+
+impl InputSource for KeyHolds<'_> {
+    fn poll(&mut self, frame: u64, keyboard: &mut Keyboard) -> Vec<FrontendEvent> {
+        for KeyHold(from, to, key) in self.0 {
+            if frame == *from {
+                keyboard.press(*key);
+            }
+            if frame == *to {
+                keyboard.release(*key);
+            }
+        }
+        Vec::new()
+    }
+}
+
+fn load(rom: &str, profile: QuirksProfile) -> CPU<HeadlessDisplay> {
+    let mut memory = Memory::new();
+    memory
+        .load(format!("roms/tests/{rom}.ch8").as_str())
+        .expect("Couldn't load test ROM");
+    let display = HeadlessDisplay::new(Palette::default());
+    CPU::new(memory, display, Keyboard::new(), Quirks::new(profile))
+}
+
+fn run(rom: &str, profile: QuirksProfile, frames: u32, keys: &[KeyHold]) -> CPU<HeadlessDisplay> {
+    let mut cpu = load(rom, profile);
+    let mut audio: Box<dyn AudioSink> = Box::new(NullAudio);
+    cpu.run_frames(frames, &mut KeyHolds(keys), &mut audio);
+    cpu
+}
+
+fn screen(cpu: &CPU<HeadlessDisplay>) -> String {
+    let framebuffer = cpu.framebuffer();
+    (0..HEIGHT)
+        .map(|y| {
+            (0..WIDTH)
+                .map(|x| if framebuffer.is_lit(x, y) { '#' } else { '.' })
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// The 8x5 glyphs the ROMs draw left to right from (0, 1), 8 to a row and 6 rows apart, up to
+// the first empty one
+fn glyphs(cpu: &CPU<HeadlessDisplay>) -> Vec<[u8; 5]> {
+    let framebuffer = cpu.framebuffer();
+    let mut glyphs = Vec::new();
+    for cell in 0..(WIDTH / 8) * ((HEIGHT - 1) / 6) {
+        let (x, y) = (cell % 8 * 8, 1 + cell / 8 * 6);
+        let mut glyph = [0; 5];
+        for (row, byte) in glyph.iter_mut().enumerate() {
+            for bit in 0..8 {
+                if framebuffer.is_lit(x + bit, y + row) {
+                    *byte |= 0x80 >> bit;
+                }
+            }
+        }
+        if glyph == [0; 5] {
+            break;
+        }
+        glyphs.push(glyph);
+    }
+    glyphs
+}
+
+// The hex digits of the built-in font, which the ROMs draw with Fx29
+fn digits(digits: &[u8]) -> Vec<[u8; 5]> {
+    let font = Font::default();
+    digits
+        .iter()
+        .map(|digit| {
+            let start = *digit as usize * 5;
+            font.glyphs()[start..start + 5].try_into().unwrap()
+        })
+        .collect()
+}
+
+fn assert_passes(rom: &str, frames: u32, checks: usize) {
+    for profile in PROFILES {
+        let cpu = run(rom, profile, frames, &[]);
+        let glyphs = glyphs(&cpu);
+        let failed = glyphs.iter().filter(|glyph| **glyph == CROSS).count();
+        assert_eq!(
+            failed, 0,
+            "{rom} with {profile:?} quirks failed {failed} checks"
+        );
+        assert_eq!(glyphs, vec![TICK; checks], "{rom} with {profile:?} quirks");
+    }
+}
+
+fn expected(name: &str) -> String {
+    fs::read_to_string(format!("roms/tests/expected/{name}.txt"))
+        .expect("Couldn't read expected screen")
+        .trim_end()
+        .to_string()
+}
+
+#[test]
+fn logo_rom_draws_logo() {
+    for profile in PROFILES {
+        let cpu = run("logo", profile, 10, &[]);
+        assert_eq!(screen(&cpu), expected("logo"), "{profile:?}");
+    }
+}
+
+#[test]
+fn opcodes_rom_passes() {
+    assert_passes("opcodes", 60, 18);
+}
+
+#[test]
+fn flags_rom_passes() {
+    assert_passes("flags", 60, 24);
+}
+
+#[test]
+fn keypad_rom_shows_pressed_keys() {
+    let keys = [
+        KeyHold(2, 14, 0xA),
+        KeyHold(20, 32, 0x3),
+        KeyHold(40, 52, 0x3),
+    ];
+    for profile in PROFILES {
+        let cpu = run("keypad", profile, 60, &keys);
+        assert_eq!(glyphs(&cpu), digits(&[0xA, 0x3, 0x3]), "{profile:?}");
+    }
+}
+
+#[test]
+fn beep_rom_beeps_for_thirty_frames() {
+    for profile in PROFILES {
+        let cpu = run("beep", profile, 1, &[]);
+        assert!(cpu.buzzer_on());
+        let cpu = run("beep", profile, 30, &[]);
+        assert!(!cpu.buzzer_on());
+        assert_eq!(glyphs(&cpu), digits(&[0xB]));
+    }
+}
+
+#[test]
+fn beep_rom_writes_thirty_frames_of_audio() {
+    let path = env::temp_dir().join("chip-8-emulator-beep-test.wav");
+    let mut cpu = load("beep", QuirksProfile::default());
+    let mut audio: Box<dyn AudioSink> = Box::new(WavAudio::create(&path, Tone::default()).unwrap());
+    cpu.run_frames(40, &mut KeyHolds(&[]), &mut audio);
+    audio.finish().unwrap();
+    let mut reader = hound::WavReader::open(&path).unwrap();
+    let samples: Vec<i16> = reader.samples().map(Result::unwrap).collect();
+    assert_eq!(samples.len(), 40 * SAMPLES_PER_FRAME as usize);
+    // The tone fades out at the start of the frame after the last one, so the frames still
+    // sounding at their end are the frames the buzzer was on
+    let beeping: Vec<bool> = samples
+        .chunks(SAMPLES_PER_FRAME as usize)
+        .map(|frame| frame[frame.len() - 1] != 0)
+        .collect();
+    assert_eq!(beeping.iter().filter(|on| **on).count(), 30);
+    assert!(beeping[..30].iter().all(|on| *on));
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn quirks_rom_reports_profile() {
+    // VF reset, memory increment, shifting Vy, jumping with Vx and clipping, as the COSMAC VIP
+    // and SUPER-CHIP 1.1 documentation describe them, and none at all under Cowgod's reference
+    let cases = [
+        (QuirksProfile::Cowgod, [0, 0, 0, 0, 0]),
+        (QuirksProfile::Vip, [1, 1, 1, 0, 1]),
+        (QuirksProfile::Schip, [0, 0, 0, 1, 1]),
+    ];
+    for (profile, detected) in cases {
+        let cpu = run("quirks", profile, 30, &[]);
+        assert_eq!(glyphs(&cpu), digits(&detected), "{profile:?}");
+    }
+}
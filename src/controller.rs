@@ -0,0 +1,327 @@
+use crate::keyboard::Keyboard;
+use crate::keymap::{Keymap, KeypadConfig};
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::{GameControllerSubsystem, Sdl};
+use serde::Deserialize;
+
+const DEFAULT_THRESHOLD: f32 = 0.5;
+// SDL's names for controller buttons, the left stick also presses the d-pad ones
+const BUTTONS: [(&str, Button); 15] = [
+    ("a", Button::A),
+    ("b", Button::B),
+    ("x", Button::X),
+    ("y", Button::Y),
+    ("back", Button::Back),
+    ("guide", Button::Guide),
+    ("start", Button::Start),
+    ("leftstick", Button::LeftStick),
+    ("rightstick", Button::RightStick),
+    ("leftshoulder", Button::LeftShoulder),
+    ("rightshoulder", Button::RightShoulder),
+    ("dpup", Button::DPadUp),
+    ("dpdown", Button::DPadDown),
+    ("dpleft", Button::DPadLeft),
+    ("dpright", Button::DPadRight),
+];
+// Most games move with 2, 4, 6 and 8 and act with 5
+const DEFAULT_BUTTONS: [(&str, &[&str]); 5] = [
+    ("2", &["dpup"]),
+    ("4", &["dpleft"]),
+    ("6", &["dpright"]),
+    ("8", &["dpdown"]),
+    ("5", &["a", "b"]),
+];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControllerConfig {
+    // How far the left stick has to be pushed to press the d-pad, from 0 to 1
+    pub threshold: Option<f32>,
+    pub buttons: KeypadConfig,
+}
+
+pub struct ControllerMapping {
+    buttons: Keymap,
+    threshold: i16,
+}
+
+impl ControllerMapping {
+    // The default mapping with `configs` on top, later ones winning
+    pub fn new(configs: &[&ControllerConfig]) -> Result<Self, String> {
+        let mut buttons = Keymap::empty();
+        buttons.apply(
+            &DEFAULT_BUTTONS
+                .iter()
+                .map(|(key, buttons)| {
+                    let buttons = buttons.iter().map(|button| button.to_string()).collect();
+                    (key.to_string(), buttons)
+                })
+                .collect(),
+        )?;
+        let mut threshold = DEFAULT_THRESHOLD;
+        for config in configs {
+            buttons.apply(&config.buttons)?;
+            threshold = config.threshold.unwrap_or(threshold);
+        }
+        buttons.check(|name| button(name).is_some())?;
+        if !(0.0..=1.0).contains(&threshold) {
+            return Err(format!(
+                "Controller threshold must be between 0 and 1: {threshold}"
+            ));
+        }
+        Ok(Self {
+            buttons,
+            threshold: (threshold * i16::MAX as f32) as i16,
+        })
+    }
+}
+
+impl Default for ControllerMapping {
+    fn default() -> Self {
+        Self::new(&[]).unwrap()
+    }
+}
+
+fn button(name: &str) -> Option<Button> {
+    BUTTONS
+        .iter()
+        .find(|(button, _)| button.eq_ignore_ascii_case(name))
+        .map(|(_, button)| *button)
+}
+
+fn button_name(button: Button) -> Option<&'static str> {
+    BUTTONS
+        .iter()
+        .find(|(_, other)| *other == button)
+        .map(|(name, _)| *name)
+}
+
+// The d-pad buttons an axis of the left stick presses, and whether it is pushed far enough for each
+fn stick_directions(axis: Axis, value: i16, threshold: i16) -> Option<[(&'static str, bool); 2]> {
+    let negative = value as i32 <= -(threshold as i32);
+    let positive = value >= threshold;
+    match axis {
+        Axis::LeftX => Some([("dpleft", negative), ("dpright", positive)]),
+        Axis::LeftY => Some([("dpup", negative), ("dpdown", positive)]),
+        _ => None,
+    }
+}
+
+// Buttons held on each controller, by joystick id, and whether they came from the stick.
+// Keys are only pressed and released when a button changes, so a resting stick never lets go of
+// the d-pad, and unplugging a controller releases everything it held
+#[derive(Default)]
+struct HeldButtons {
+    held: Vec<(u32, &'static str, bool)>,
+}
+
+impl HeldButtons {
+    fn set(
+        &mut self,
+        button: (u32, &'static str, bool),
+        down: bool,
+        mapping: &ControllerMapping,
+        keyboard: &mut Keyboard,
+    ) {
+        let held = self.held.contains(&button);
+        let Some(key) = mapping.buttons.key(button.1) else {
+            return;
+        };
+        if down && !held {
+            self.held.push(button);
+            keyboard.press(key);
+        } else if !down && held {
+            self.held.retain(|other| *other != button);
+            // Another held button, or the stick, may still be pressing the same key
+            let still_held = self
+                .held
+                .iter()
+                .any(|other| mapping.buttons.key(other.1) == Some(key));
+            if !still_held {
+                keyboard.release(key);
+            }
+        }
+    }
+    fn release_all(&mut self, which: u32, mapping: &ControllerMapping, keyboard: &mut Keyboard) {
+        let buttons: Vec<_> = self
+            .held
+            .iter()
+            .filter(|(other, _, _)| *other == which)
+            .copied()
+            .collect();
+        for button in buttons {
+            self.set(button, false, mapping, keyboard);
+        }
+    }
+}
+
+pub struct Controllers {
+    subsystem: GameControllerSubsystem,
+    open: Vec<GameController>,
+    mapping: ControllerMapping,
+    held: HeldButtons,
+}
+
+impl Controllers {
+    pub fn new(sdl_context: &Sdl, mapping: ControllerMapping) -> Result<Self, String> {
+        Ok(Self {
+            subsystem: sdl_context.game_controller()?,
+            open: Vec::new(),
+            mapping,
+            held: HeldButtons::default(),
+        })
+    }
+    // SDL also sends an added event for every controller connected at start up
+    pub fn handle_event(&mut self, event: &Event, keyboard: &mut Keyboard) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => match self.subsystem.open(which) {
+                Ok(controller) => self.open.push(controller),
+                Err(e) => eprintln!("Couldn't open controller {which}: {e}"),
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.open
+                    .retain(|controller| controller.instance_id() != which);
+                self.held.release_all(which, &self.mapping, keyboard);
+            }
+            Event::ControllerButtonDown { which, button, .. } => {
+                if let Some(name) = button_name(button) {
+                    self.held
+                        .set((which, name, false), true, &self.mapping, keyboard);
+                }
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                if let Some(name) = button_name(button) {
+                    self.held
+                        .set((which, name, false), false, &self.mapping, keyboard);
+                }
+            }
+            Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } => {
+                let directions = stick_directions(axis, value, self.mapping.threshold);
+                for (name, down) in directions.into_iter().flatten() {
+                    self.held
+                        .set((which, name, true), down, &self.mapping, keyboard);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    #[test]
+    fn should_map_dpad_and_face_buttons_by_default() {
+        let mapping = ControllerMapping::default();
+        assert_eq!(mapping.buttons.key("dpup"), Some(0x2));
+        assert_eq!(mapping.buttons.key("dpright"), Some(0x6));
+        assert_eq!(mapping.buttons.key("a"), Some(0x5));
+        assert_eq!(mapping.buttons.key("start"), None);
+    }
+    #[test]
+    fn should_apply_rom_mappings_over_the_global_one() {
+        let global = ControllerConfig {
+            threshold: Some(0.25),
+            buttons: BTreeMap::from([("A".to_string(), vec!["start".to_string()])]),
+        };
+        let rom = ControllerConfig {
+            threshold: None,
+            buttons: BTreeMap::from([("6".to_string(), vec!["a".to_string()])]),
+        };
+        let mapping = ControllerMapping::new(&[&global, &rom]).unwrap();
+        assert_eq!(mapping.buttons.key("start"), Some(0xA));
+        assert_eq!(mapping.buttons.key("a"), Some(0x6));
+        assert_eq!(mapping.buttons.key("b"), Some(0x5));
+        assert_eq!(mapping.threshold, (0.25 * i16::MAX as f32) as i16);
+        let unknown = ControllerConfig {
+            threshold: None,
+            buttons: BTreeMap::from([("5".to_string(), vec!["triangle".to_string()])]),
+        };
+        assert!(ControllerMapping::new(&[&unknown]).is_err());
+    }
+    #[test]
+    fn should_press_the_dpad_past_the_stick_threshold() {
+        let threshold = i16::MAX / 2;
+        assert_eq!(
+            stick_directions(Axis::LeftX, -20000, threshold),
+            Some([("dpleft", true), ("dpright", false)])
+        );
+        assert_eq!(
+            stick_directions(Axis::LeftY, 1000, threshold),
+            Some([("dpup", false), ("dpdown", false)])
+        );
+        assert_eq!(stick_directions(Axis::RightX, i16::MAX, threshold), None);
+    }
+    #[test]
+    fn should_release_keys_only_when_their_button_changes() {
+        let mapping = ControllerMapping::default();
+        let mut held = HeldButtons::default();
+        let mut keyboard = Keyboard::new();
+        held.set((0, "dpleft", false), true, &mapping, &mut keyboard);
+        // A resting stick doesn't let go of the d-pad
+        held.set((0, "dpleft", true), false, &mapping, &mut keyboard);
+        assert!(keyboard.is_pressed(0x4));
+        held.set((1, "a", false), true, &mapping, &mut keyboard);
+        held.release_all(1, &mapping, &mut keyboard);
+        assert!(!keyboard.is_pressed(0x5));
+        assert!(keyboard.is_pressed(0x4));
+    }
+    #[test]
+    fn should_keep_a_key_pressed_while_another_button_holds_it() {
+        let mapping = ControllerMapping::default();
+        let mut held = HeldButtons::default();
+        let mut keyboard = Keyboard::new();
+        held.set((0, "a", false), true, &mapping, &mut keyboard);
+        held.set((0, "b", false), true, &mapping, &mut keyboard);
+        held.set((0, "a", false), false, &mapping, &mut keyboard);
+        assert!(keyboard.is_pressed(0x5));
+        held.set((0, "b", false), false, &mapping, &mut keyboard);
+        assert!(!keyboard.is_pressed(0x5));
+        // The d-pad and the stick share keys too
+        held.set((0, "dpleft", false), true, &mapping, &mut keyboard);
+        held.set((0, "dpleft", true), true, &mapping, &mut keyboard);
+        held.set((0, "dpleft", false), false, &mapping, &mut keyboard);
+        assert!(keyboard.is_pressed(0x4));
+        held.set((0, "dpleft", true), false, &mapping, &mut keyboard);
+        assert!(!keyboard.is_pressed(0x4));
+    }
+    #[test]
+    fn should_press_keys_from_a_virtual_controller() {
+        use sdl2::sys;
+        let sdl_context = sdl2::init().unwrap();
+        let mut controllers = Controllers::new(&sdl_context, ControllerMapping::default()).unwrap();
+        let mut event_pump = sdl_context.event_pump().unwrap();
+        let mut keyboard = Keyboard::new();
+        let mut pump = |controllers: &mut Controllers, keyboard: &mut Keyboard| {
+            for event in event_pump.poll_iter() {
+                controllers.handle_event(&event, keyboard);
+            }
+        };
+        let index = unsafe {
+            sys::SDL_JoystickAttachVirtual(
+                sys::SDL_JoystickType::SDL_JOYSTICK_TYPE_GAMECONTROLLER,
+                6,
+                15,
+                0,
+            )
+        };
+        assert!(index >= 0);
+        pump(&mut controllers, &mut keyboard);
+        assert_eq!(controllers.open.len(), 1);
+        let joystick =
+            unsafe { sys::SDL_JoystickFromInstanceID(controllers.open[0].instance_id() as i32) };
+        unsafe { sys::SDL_JoystickSetVirtualButton(joystick, Button::A as i32, 1) };
+        unsafe { sys::SDL_JoystickSetVirtualAxis(joystick, Axis::LeftX as i32, i16::MIN) };
+        pump(&mut controllers, &mut keyboard);
+        assert!(keyboard.is_pressed(0x5));
+        assert!(keyboard.is_pressed(0x4));
+        unsafe { sys::SDL_JoystickDetachVirtual(index) };
+        pump(&mut controllers, &mut keyboard);
+        assert!(controllers.open.is_empty());
+        assert!(!keyboard.is_any_pressed());
+    }
+}
@@ -1,39 +1,71 @@
+use crate::audio::{AudioSink, NullAudio, Tone};
 use crate::debugger::{DebuggerAction, ShowArgs, HELP_MESSAGE};
-use crate::display::DisplayTrait;
 use crate::display::HEIGHT;
 use crate::display::WIDTH;
+use crate::display::{DisplayTrait, Status};
+use crate::framebuffer::Framebuffer;
+use crate::input::{FrontendEvent, InputSource};
 use crate::keyboard::Keyboard;
 use crate::memory::Memory;
+use crate::opcode::Opcode;
+use crate::protection::{Action, Protection, Violation};
+use crate::quirks::Quirks;
+use crate::recorder::Recorder;
+use crate::screenshot;
 use colored::Colorize;
+use crossterm::terminal;
 use rand::Rng;
-use rodio::{source::SineWave, OutputStreamHandle, OutputStream, Sink};
-use sdl2;
-use sdl2::Sdl;
+use std::collections::HashSet;
 use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::time::{Duration, Instant};
 
-#[derive(Debug)]
-enum Instruction {
-    Jump(u16),
-    SkipEqual,
-    Load,
-    Display,
-    Add,
-    Call,
-    Or,
-    And,
-    XOR,
-    Sub,
-    SHR,
-    SubN,
-    SHL,
-    SNE,
-    RND,
-    SKP,
-    SKNP,
-    CLS,
-    RET,
+pub const INSTRUCTIONS_PER_FRAME: usize = 8;
+const FRAME_DURATION: Duration = Duration::from_micros(1_000_000 / 60);
+const SPEED_FACTOR: u32 = 4;
+
+// Turbo and slow motion change how often frames run, never what happens inside one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SpeedMode {
+    #[default]
+    Normal,
+    Turbo,
+    SlowMotion,
+}
+
+impl SpeedMode {
+    fn frame_duration(self) -> Duration {
+        match self {
+            Self::Normal => FRAME_DURATION,
+            Self::Turbo => FRAME_DURATION / SPEED_FACTOR,
+            Self::SlowMotion => FRAME_DURATION * SPEED_FACTOR,
+        }
+    }
+}
+
+// Fx0A stays on the same instruction until a key goes down, and with the VIP quirk until
+// it comes back up. Timers keep ticking meanwhile since frames still end as usual
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KeyWait {
+    // Keys already held when Fx0A ran only count once they are pressed again
+    Press { x: usize, keys: [bool; 16] },
+    Release { x: usize, key: u8 },
+}
+
+// Why the emulator stopped running instructions, from strict mode or an opcode it can't run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Halt {
+    Break,
+    Error,
+}
+
+// One line of the debugger's trace, like `6A11 LD VA, 0x11`
+fn trace(word: u16, opcode: Option<Opcode>) -> String {
+    match opcode {
+        Some(opcode) => format!("{word:04X} {opcode}"),
+        None => format!("{word:04X} ???"),
+    }
 }
 
 pub struct CPU<D: DisplayTrait> {
@@ -44,64 +76,93 @@ pub struct CPU<D: DisplayTrait> {
     dt: u8,
     st: u8,
     memory: Memory,
+    framebuffer: Framebuffer,
     display: D,
     keyboard: Keyboard,
-    waiting_key: bool,
-    dt_start: Instant,
-    st_start: Instant,
-    output_stream: OutputStream,
-    output_stream_handle: OutputStreamHandle,
-    sink: Sink,
+    key_wait: Option<KeyWait>,
+    frame: u64,
+    paused: bool,
+    muted: bool,
+    speed_mode: SpeedMode,
+    fps: f32,
+    quirks: Quirks,
+    tick_rate: usize,
+    screenshot_scale: usize,
+    recorder: Option<Recorder>,
+    record_scale: usize,
+    // Whether the buzzer sounded in each frame run since the frontend's sink last got them
+    buzzer_frames: Vec<bool>,
+    protection: Protection,
+    warned: HashSet<Violation>,
+    halt: Option<Halt>,
 }
 
 impl<D: DisplayTrait> CPU<D> {
-    pub fn new(memory: Memory, display: D, keyboard: Keyboard) -> Self {
-        let (output_stream, output_stream_handle) = OutputStream::try_default().unwrap();
-        let sink = Sink::try_new(&output_stream_handle).unwrap();
-        sink.append(SineWave::new(440.0));
-        sink.pause();
+    pub fn new(memory: Memory, display: D, keyboard: Keyboard, quirks: Quirks) -> Self {
         Self {
             v: [0; 16],
             i: 0,
-            pc: 0x200,
+            pc: memory.entry(),
             sp: 0,
             dt: 0,
             st: 0,
             memory,
+            framebuffer: Framebuffer::new(),
             display,
             keyboard,
-            waiting_key: false,
-            dt_start: Instant::now(),
-            st_start: Instant::now(),
-            output_stream,
-            output_stream_handle ,
-            sink
+            key_wait: None,
+            frame: 0,
+            paused: false,
+            muted: false,
+            speed_mode: SpeedMode::default(),
+            fps: 0.0,
+            quirks,
+            tick_rate: INSTRUCTIONS_PER_FRAME,
+            screenshot_scale: 1,
+            recorder: None,
+            record_scale: 1,
+            buzzer_frames: Vec::new(),
+            protection: Protection::default(),
+            warned: HashSet::new(),
+            halt: None,
         }
     }
-    pub fn run_debug(&mut self, sdl_context: &Sdl) {
+    pub fn run_debug(&mut self, input: &mut impl InputSource, audio: &mut Box<dyn AudioSink>) {
         let mut action = String::new();
         loop {
-            self.keyboard.update(sdl_context);
+            let events = input.poll(self.frame, &mut self.keyboard);
+            self.handle_frontend_events(events);
+            self.play_sound(audio);
             let debugger_prefix = "(chip-8-debugger) ".purple().magenta();
             print!("{debugger_prefix}");
             io::stdout().flush().expect("Failed to flush stdout");
             action.clear();
+            // The terminal frontend reads keys in raw mode, the prompt reads whole lines
+            let raw_mode = terminal::is_raw_mode_enabled().unwrap_or(false);
+            if raw_mode {
+                let _ = terminal::disable_raw_mode();
+            }
             io::stdin()
                 .read_line(&mut action)
                 .expect("Failed to read line");
+            if raw_mode {
+                let _ = terminal::enable_raw_mode();
+            }
             println!("");
             match DebuggerAction::from_str(action.as_str()) {
                 Ok(debugger_action) => match debugger_action {
                     DebuggerAction::Step => {
-                        println!("{:02X?}", self.cycle());
+                        let (word, opcode) = self.cycle();
+                        println!("{}", trace(word, opcode));
                     }
                     DebuggerAction::Show(arg) => match arg {
                         ShowArgs::PC => {
                             println!("{:02X?}", self.pc);
                         }
-                        ShowArgs::Mem(addr) => {
-                            println!("{:02X}", self.memory.memory[addr]);
-                        }
+                        ShowArgs::Mem(addr) => match self.memory.memory.get(addr) {
+                            Some(byte) => println!("{:02X}", byte),
+                            None => println!("0x{addr:X} is past the end of memory"),
+                        },
                         ShowArgs::Stack(addr) => {
                             println!("{:02X}", self.memory.stack[addr]);
                         }
@@ -121,21 +182,38 @@ impl<D: DisplayTrait> CPU<D> {
                             println!("{:02X?}", self.st);
                         }
                         ShowArgs::WaitingKey => {
-                            println!("{:02X?}", self.waiting_key);
+                            println!("{:02X?}", self.key_wait);
                         }
                     },
                     DebuggerAction::Run => {
                         let mut start = Instant::now();
                         loop {
-                            if start.elapsed() >= Duration::from_millis(1000 / 500) {
-                                if self.keyboard.update(sdl_context) {
+                            if start.elapsed() >= FRAME_DURATION {
+                                let events = input.poll(self.frame, &mut self.keyboard);
+                                if self.handle_frontend_events(events) {
                                     break;
                                 }
-                                println!("{:?}", self.cycle());
+                                for _ in 0..self.tick_rate {
+                                    let (word, opcode) = self.cycle();
+                                    // Raw mode doesn't go back to the start of the line by itself
+                                    print!("{}\r\n", trace(word, opcode));
+                                    if self.halt.is_some() {
+                                        break;
+                                    }
+                                }
+                                self.end_frame();
+                                self.play_sound(audio);
                                 start = Instant::now();
+                                if self.halt.is_some() {
+                                    break;
+                                }
                             }
                         }
                     },
+                    DebuggerAction::Screenshot(path, scale) => {
+                        let scale = scale.unwrap_or(self.screenshot_scale);
+                        self.print_screenshot(path.as_deref().map(Path::new), scale);
+                    }
                     DebuggerAction::Quit => {
                         break;
                     }
@@ -148,412 +226,787 @@ impl<D: DisplayTrait> CPU<D> {
                 }
             }
             println!("");
+            // Breaks go back to the prompt, errors end the session
+            match self.halt {
+                Some(Halt::Break) => self.halt = None,
+                Some(Halt::Error) => break,
+                None => {}
+            }
+        }
+        self.stop_recording();
+    }
+    // The opcode that ran, or None when it couldn't be decoded
+    fn cycle(&mut self) -> (u16, Option<Opcode>) {
+        let pc = self.pc;
+        let (lhs, rhs) = self.memory.fetch(pc);
+        let word = u16::from_be_bytes([lhs, rhs]);
+        let opcode = match Opcode::decode(word) {
+            Ok(opcode) => opcode,
+            Err(e) => {
+                self.check_memory(pc);
+                self.halt(Halt::Error, format!("0x{pc:03X}: {e}").as_str());
+                return (word, None);
+            }
+        };
+        if let Some(key_wait) = self.key_wait {
+            self.key_wait = self.wait_for_key(key_wait);
+        } else if let Err(e) = self.execute(opcode) {
+            self.halt(Halt::Error, format!("0x{pc:03X}: {e}").as_str());
+        }
+        self.check_memory(pc);
+        (word, Some(opcode))
+    }
+    // Stops running instructions and says why on the terminal and the OSD
+    fn halt(&mut self, halt: Halt, message: &str) {
+        match halt {
+            Halt::Break => {
+                eprintln!("Break: {message}");
+                self.halt = self.halt.or(Some(Halt::Break));
+            }
+            Halt::Error => {
+                eprintln!("Error: {message}");
+                self.halt = Some(Halt::Error);
+            }
+        }
+        self.display.show_message(message);
+    }
+    // Acts on what the instruction at `pc` did to memory, as strict mode was told to
+    fn check_memory(&mut self, pc: u16) {
+        for violation in self.memory.take_violations() {
+            let action = self.protection.action(violation);
+            if action == Action::Off || (action == Action::Warn && !self.warned.insert(violation)) {
+                continue;
+            }
+            let message = format!("0x{pc:03X}: {violation}");
+            match action {
+                Action::Off => {}
+                Action::Warn => eprintln!("Warning: {message}"),
+                Action::Break => self.halt(Halt::Break, message.as_str()),
+                Action::Error => self.halt(Halt::Error, message.as_str()),
+            }
+        }
+    }
+    // Moves Fx0A along with the keys held now, storing the key and going past it once done
+    fn wait_for_key(&mut self, key_wait: KeyWait) -> Option<KeyWait> {
+        let keys = self.keyboard.keys();
+        let (x, key) = match key_wait {
+            KeyWait::Press { x, keys: before } => {
+                let Some(key) = (0..16).find(|&key| keys[key] && !before[key]) else {
+                    return Some(KeyWait::Press { x, keys });
+                };
+                if self.quirks.key_wait_release {
+                    return Some(KeyWait::Release { x, key: key as u8 });
+                }
+                (x, key as u8)
+            }
+            KeyWait::Release { x, key } => {
+                if keys[key as usize] {
+                    return Some(key_wait);
+                }
+                (x, key)
+            }
+        };
+        self.v[x] = key;
+        self.increment_pc();
+        None
+    }
+    pub fn run_frame(&mut self) {
+        for _ in 0..self.tick_rate {
+            self.cycle();
+            if self.halt.is_some() {
+                break;
+            }
         }
+        self.end_frame();
     }
-    fn cycle(&mut self) -> (Instruction, String) {
-        if self.waiting_key && self.keyboard.is_any_pressed() {
-            self.increment_pc();
-            self.waiting_key = false;
+    // As fast as possible, for headless runs, which stop at breaks since nobody can resume them
+    pub fn run_frames(
+        &mut self,
+        frames: u32,
+        input: &mut impl InputSource,
+        audio: &mut Box<dyn AudioSink>,
+    ) {
+        for _ in 0..frames {
+            let events = input.poll(self.frame, &mut self.keyboard);
+            if self.handle_frontend_events(events) {
+                break;
+            }
+            self.run_frame();
+            self.play_sound(audio);
+            if self.halt.is_some() {
+                break;
+            }
         }
-        let lhs = self.memory.memory[self.pc as usize];
-        let rhs = self.memory.memory[(self.pc + 1) as usize];
-        let instruction = self.decode(lhs, rhs);
-        if self.dt > 0 && self.dt_start.elapsed() >= Duration::from_millis(1000 / 60) {
-            self.decrement_dt();
-            self.dt_start = Instant::now();
+        self.play_sound(audio);
+    }
+    fn end_frame(&mut self) {
+        self.frame += 1;
+        // The buzzer sounds for the whole frame ST was non-zero in, so ST = N beeps for
+        // exactly N frames
+        let buzzer_on = self.buzzer_on();
+        self.tick_timers();
+        self.display.show_keys(self.keyboard.keys());
+        self.display.end_frame(&self.framebuffer);
+        self.buzzer_frames.push(buzzer_on);
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.frame(&self.framebuffer, buzzer_on) {
+                eprintln!("Couldn't record frame, recording stopped: {e}");
+                self.recorder = None;
+            }
+        }
+    }
+    // Hands the frontend's sink the buzzer of every frame run since the last call, and keeps it
+    // quiet while muted or paused
+    fn play_sound(&mut self, audio: &mut Box<dyn AudioSink>) {
+        audio.set_muted(self.muted);
+        for buzzer_on in self.buzzer_frames.drain(..) {
+            if let Err(e) = audio.frame(buzzer_on) {
+                eprintln!("Couldn't play the buzzer, audio stopped: {e}");
+                *audio = Box::new(NullAudio);
+            }
         }
-        if self.st > 0 && self.st_start.elapsed() >= Duration::from_millis(1000 / 60) {
-            // TODO (luizf): Implement sound
-            self.decrement_st();
-            self.st_start = Instant::now();
+        if self.paused {
+            audio.pause();
         }
-        return (instruction, format!("{:02X?}{:02X?}", lhs, rhs));
     }
-    pub fn run(&mut self, sdl_context: &Sdl) {
+    pub fn run(&mut self, input: &mut impl InputSource, audio: &mut Box<dyn AudioSink>) {
         let mut start = Instant::now();
+        let mut status_start = Instant::now();
+        let mut frames = 0;
         loop {
-            if start.elapsed() >= Duration::from_millis(1000 / 500) {
-                if self.keyboard.update(sdl_context) {
+            if start.elapsed() >= self.speed_mode.frame_duration() {
+                let events = input.poll(self.frame, &mut self.keyboard);
+                if self.handle_frontend_events(events) {
                     break;
                 }
-                self.cycle();
+                // After an error the window stays open with the message until it is closed
+                if self.halt == Some(Halt::Error) {
+                    self.display.end_frame(&self.framebuffer);
+                } else if !self.paused {
+                    self.run_frame();
+                    frames += 1;
+                    if self.halt == Some(Halt::Break) {
+                        self.halt = None;
+                        self.set_paused(true);
+                    }
+                }
+                self.play_sound(audio);
                 start = Instant::now();
             }
+            if status_start.elapsed() >= Duration::from_secs(1) {
+                self.fps = frames as f32 / status_start.elapsed().as_secs_f32();
+                self.show_status();
+                frames = 0;
+                status_start = Instant::now();
+            }
         }
+        self.stop_recording();
     }
-    fn decode(&mut self, lhs: u8, rhs: u8) -> Instruction {
-        let op = Self::get_leftmost_nibble(lhs);
-        match op {
-            0x0 => match rhs {
-                0xE0 => {
-                    self.display.clear();
-                    self.increment_pc();
-                    self.display.draw();
-                    Instruction::CLS
+    fn show_status(&mut self) {
+        self.display.show_status(Status {
+            speed: self.fps / 60.0,
+            fps: self.fps,
+            ips: self.fps * self.tick_rate as f32,
+            speed_mode: self.speed_mode,
+            paused: self.paused,
+        });
+    }
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.display
+            .show_message(if paused { "Paused" } else { "Resumed" });
+        self.show_status();
+    }
+    // Toggling the mode that is on goes back to normal speed
+    fn toggle_speed_mode(&mut self, speed_mode: SpeedMode) {
+        self.speed_mode = if self.speed_mode == speed_mode {
+            SpeedMode::Normal
+        } else {
+            speed_mode
+        };
+        let message = match self.speed_mode {
+            SpeedMode::Normal => "Normal speed".to_string(),
+            SpeedMode::Turbo => format!("Turbo {SPEED_FACTOR}x"),
+            SpeedMode::SlowMotion => format!("Slow motion 1/{SPEED_FACTOR}x"),
+        };
+        self.display.show_message(message.as_str());
+        self.show_status();
+    }
+    // Soft reset, the ROM is loaded again and every register cleared without restarting the process
+    pub fn reset(&mut self) -> Result<(), Box<dyn std::error::Error + 'static>> {
+        self.memory.reload()?;
+        self.v = [0; 16];
+        self.i = 0;
+        self.pc = self.memory.entry();
+        self.sp = 0;
+        self.dt = 0;
+        self.st = 0;
+        self.key_wait = None;
+        self.framebuffer.clear();
+        self.display.draw(&self.framebuffer);
+        Ok(())
+    }
+    // Returns whether the frontend asked to quit
+    fn handle_frontend_events(&mut self, events: Vec<FrontendEvent>) -> bool {
+        let mut quit = false;
+        for event in events {
+            match event {
+                FrontendEvent::Quit => quit = true,
+                FrontendEvent::Screenshot => self.print_screenshot(None, self.screenshot_scale),
+                FrontendEvent::ToggleRecording => self.toggle_recording(),
+                FrontendEvent::TogglePause => self.set_paused(!self.paused),
+                FrontendEvent::ToggleMute => {
+                    self.muted = !self.muted;
+                    self.display
+                        .show_message(if self.muted { "Muted" } else { "Sound on" });
                 }
-                0xEE => {
-                    // (luizf): Different from Cowgod's reference
-                    if self.sp == 0 {
-                        panic!("Stack Underflow!")
+                // Only a paused CPU steps, not one halted by an error
+                FrontendEvent::FrameAdvance if self.halt != Some(Halt::Error) => {
+                    if !self.paused {
+                        self.set_paused(true);
+                    }
+                    self.run_frame();
+                    // A break cuts the frame short, the CPU is paused already
+                    if self.halt == Some(Halt::Break) {
+                        self.halt = None;
                     }
-                    self.sp -= 1;
-                    self.pc = self.memory.stack[self.sp as usize];
-                    self.increment_pc();
-                    Instruction::RET
                 }
+                FrontendEvent::FrameAdvance => {}
+                FrontendEvent::Reset => match self.reset() {
+                    Ok(()) => self.display.show_message("Reset"),
+                    Err(e) => eprintln!("Couldn't reset: {e}"),
+                },
+                FrontendEvent::ToggleTurbo => self.toggle_speed_mode(SpeedMode::Turbo),
+                FrontendEvent::ToggleSlowMotion => self.toggle_speed_mode(SpeedMode::SlowMotion),
                 _ => {
-                    unimplemented!("Unimplemented OPCODE FOR 0");
+                    self.display.handle_event(event);
+                    self.display.draw(&self.framebuffer);
+                }
+            }
+        }
+        quit
+    }
+    fn execute(&mut self, opcode: Opcode) -> Result<(), String> {
+        match opcode {
+            Opcode::Cls => {
+                self.framebuffer.clear();
+                self.increment_pc();
+                self.display.draw(&self.framebuffer);
+            }
+            Opcode::Ret => {
+                // (luizf): Different from Cowgod's reference
+                if self.sp == 0 {
+                    return Err(format!("{opcode} with an empty stack"));
                 }
-            },
-            0x1 => {
-                let address = (((Self::get_rightmost_nibble(lhs)) as u16) << 8) | rhs as u16;
-                self.pc = address;
-                Instruction::Jump(address)
+                self.sp -= 1;
+                self.pc = self.memory.stack[self.sp as usize];
+                self.increment_pc();
+            }
+            Opcode::Jp { nnn } => {
+                self.pc = nnn;
             }
-            0x2 => {
+            Opcode::Call { nnn } => {
                 // (luizf): Different from Cowgod's reference
                 if self.sp == 15 {
-                    panic!("Stack Overflow!")
+                    return Err(format!("{opcode} overflows the stack"));
                 }
-                let address = (((Self::get_rightmost_nibble(lhs)) as u16) << 8) | rhs as u16;
                 self.memory.stack[self.sp as usize] = self.pc;
                 self.sp += 1;
-                self.pc = address;
-                Instruction::Call
+                self.pc = nnn;
             }
-            0x3 => {
-                let x = Self::get_rightmost_nibble(lhs);
-                if self.v[x as usize] == rhs {
+            Opcode::SeVxByte { x, kk } => {
+                if self.v[x as usize] == kk {
                     self.skip_next_instruction();
                 } else {
                     self.increment_pc();
                 }
-                Instruction::SkipEqual
             }
-            0x4 => {
-                let x = Self::get_rightmost_nibble(lhs);
-                if self.v[x as usize] != rhs {
+            Opcode::SneVxByte { x, kk } => {
+                if self.v[x as usize] != kk {
                     self.skip_next_instruction();
                 } else {
                     self.increment_pc();
                 }
-                Instruction::SNE
             }
-            0x5 => {
-                let x = Self::get_rightmost_nibble(lhs);
-                let y = Self::get_leftmost_nibble(rhs);
+            Opcode::SeVxVy { x, y } => {
                 if self.v[x as usize] == self.v[y as usize] {
                     self.skip_next_instruction();
                 } else {
                     self.increment_pc();
                 }
-                Instruction::SkipEqual
             }
-            0x6 => {
-                let x = Self::get_rightmost_nibble(lhs);
-                self.v[x as usize] = rhs;
+            Opcode::LdVxByte { x, kk } => {
+                self.v[x as usize] = kk;
                 self.increment_pc();
-                Instruction::Load
             }
-            0x7 => {
-                let x = Self::get_rightmost_nibble(lhs);
-                self.v[x as usize] = self.v[x as usize].wrapping_add(rhs);
+            Opcode::AddVxByte { x, kk } => {
+                self.v[x as usize] = self.v[x as usize].wrapping_add(kk);
                 self.increment_pc();
-                Instruction::Add
-            }
-            0x8 => {
-                let n = Self::get_rightmost_nibble(rhs);
-                match n {
-                    0x0 => {
-                        let x = Self::get_rightmost_nibble(lhs);
-                        let y = Self::get_leftmost_nibble(rhs);
-                        self.v[x as usize] = self.v[y as usize];
-                        self.increment_pc();
-                        Instruction::Load
-                    }
-                    0x1 => {
-                        let x = Self::get_rightmost_nibble(lhs);
-                        let y = Self::get_leftmost_nibble(rhs);
-                        self.v[x as usize] |= self.v[y as usize];
-                        self.increment_pc();
-                        Instruction::Or
-                    }
-                    0x2 => {
-                        let x = Self::get_rightmost_nibble(lhs);
-                        let y = Self::get_leftmost_nibble(rhs);
-                        self.v[x as usize] &= self.v[y as usize];
-                        self.increment_pc();
-                        Instruction::And
-                    }
-                    0x3 => {
-                        let x = Self::get_rightmost_nibble(lhs);
-                        let y = Self::get_leftmost_nibble(rhs);
-                        self.v[x as usize] ^= self.v[y as usize];
-                        self.increment_pc();
-                        Instruction::XOR
-                    }
-                    0x4 => {
-                        let x = Self::get_rightmost_nibble(lhs);
-                        let y = Self::get_leftmost_nibble(rhs);
-                        let vx = self.v[x as usize] as u16 + self.v[y as usize] as u16;
-                        self.v[x as usize] = vx as u8;
-                        self.v[0xF] = if vx > 0xFF { 1 } else { 0 };
-                        self.increment_pc();
-                        Instruction::Add
-                    }
-                    0x5 => {
-                        let x = Self::get_rightmost_nibble(lhs);
-                        let y = Self::get_leftmost_nibble(rhs);
-                        self.v[0xF] = if self.v[x as usize] > self.v[y as usize] {
-                            1
-                        } else {
-                            0
-                        };
-                        self.v[x as usize] = self.v[x as usize].wrapping_sub(self.v[y as usize]);
-                        self.increment_pc();
-                        Instruction::Sub
-                    }
-                    0x6 => {
-                        let x = Self::get_rightmost_nibble(lhs);
-                        self.v[0xF] = self.v[x as usize] & 1;
-                        self.v[x as usize] >>= 1;
-                        self.increment_pc();
-                        Instruction::SHR
-                    }
-                    0x7 => {
-                        let x = Self::get_rightmost_nibble(lhs);
-                        let y = Self::get_leftmost_nibble(rhs);
-                        self.v[0xF] = if self.v[y as usize] > self.v[x as usize] {
-                            1
-                        } else {
-                            0
-                        };
-                        self.v[x as usize] = self.v[y as usize].wrapping_sub(self.v[x as usize]);
-                        self.increment_pc();
-                        Instruction::SubN
-                    }
-                    0xE => {
-                        let x = Self::get_rightmost_nibble(lhs);
-                        self.v[0xF] = self.v[x as usize] >> 7 & 1;
-                        self.v[x as usize] <<= 1;
-                        self.increment_pc();
-                        Instruction::SHL
-                    }
-                    _ => {
-                        unimplemented!("UNIMPLEMENTED OPCODE: {:X?}", op)
-                    }
+            }
+            Opcode::LdVxVy { x, y } => {
+                self.v[x as usize] = self.v[y as usize];
+                self.increment_pc();
+            }
+            Opcode::Or { x, y } => {
+                self.v[x as usize] |= self.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
                 }
+                self.increment_pc();
+            }
+            Opcode::And { x, y } => {
+                self.v[x as usize] &= self.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+                self.increment_pc();
+            }
+            Opcode::Xor { x, y } => {
+                self.v[x as usize] ^= self.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+                self.increment_pc();
+            }
+            Opcode::AddVxVy { x, y } => {
+                let (vx, carry) = self.v[x as usize].overflowing_add(self.v[y as usize]);
+                self.v[x as usize] = vx;
+                self.v[0xF] = carry as u8;
+                self.increment_pc();
+            }
+            Opcode::Sub { x, y } => {
+                // VF is written last so it wins when x is F
+                let (vx, borrow) = self.v[x as usize].overflowing_sub(self.v[y as usize]);
+                self.v[x as usize] = vx;
+                self.v[0xF] = !borrow as u8;
+                self.increment_pc();
             }
-            0x9 => {
-                let x = Self::get_rightmost_nibble(lhs);
-                let y = Self::get_leftmost_nibble(rhs);
+            Opcode::Shr { x, y } => {
+                let source = if self.quirks.shift_uses_vy { y } else { x };
+                let flag = self.v[source as usize] & 1;
+                self.v[x as usize] = self.v[source as usize] >> 1;
+                self.v[0xF] = flag;
+                self.increment_pc();
+            }
+            Opcode::Subn { x, y } => {
+                let (vx, borrow) = self.v[y as usize].overflowing_sub(self.v[x as usize]);
+                self.v[x as usize] = vx;
+                self.v[0xF] = !borrow as u8;
+                self.increment_pc();
+            }
+            Opcode::Shl { x, y } => {
+                let source = if self.quirks.shift_uses_vy { y } else { x };
+                let flag = self.v[source as usize] >> 7 & 1;
+                self.v[x as usize] = self.v[source as usize] << 1;
+                self.v[0xF] = flag;
+                self.increment_pc();
+            }
+            Opcode::SneVxVy { x, y } => {
                 if self.v[x as usize] != self.v[y as usize] {
                     self.skip_next_instruction();
                 } else {
                     self.increment_pc();
                 }
-                Instruction::SNE
             }
-            0xA => {
-                let address = (((Self::get_rightmost_nibble(lhs)) as u16) << 8) | rhs as u16;
-                self.i = address;
+            Opcode::LdI { nnn } => {
+                self.i = nnn;
                 self.increment_pc();
-                Instruction::Load
             }
-            0xB => {
-                let address = ((((Self::get_rightmost_nibble(lhs)) as u16) << 8) | rhs as u16)
-                    + self.v[0] as u16;
-                self.pc = address;
-                Instruction::Jump(address)
+            Opcode::JpV0 { nnn } => {
+                let offset = if self.quirks.jump_uses_vx {
+                    self.v[(nnn >> 8) as usize]
+                } else {
+                    self.v[0]
+                };
+                self.pc = nnn + offset as u16;
             }
-            0xC => {
-                let x = Self::get_rightmost_nibble(lhs);
+            Opcode::Rnd { x, kk } => {
                 let random: u8 = rand::thread_rng().gen();
-                self.v[x as usize] = random & rhs;
+                self.v[x as usize] = random & kk;
                 self.increment_pc();
-                Instruction::RND
             }
-            0xD => {
-                let n = Self::get_rightmost_nibble(rhs);
-                let x = Self::get_rightmost_nibble(lhs);
-                let y = Self::get_leftmost_nibble(rhs);
-                let mut vy = self.v[y as usize];
+            Opcode::Drw { x, y, n } => {
+                let start_x = self.v[x as usize] as usize % WIDTH;
+                let start_y = self.v[y as usize] as usize % HEIGHT;
                 let mut vf_changed = false;
-                for sprite_index in self.i..(self.i + n as u16) {
-                    let start_index: usize = sprite_index as usize;
-                    if vy as usize >= HEIGHT {
-                        vy = 0;
-                    }
-                    let sprite_row = self.memory.memory[start_index];
-                    for b in 0..8 {
-                        let mut vx = self.v[x as usize];
-                        if vx as usize + b as usize >= WIDTH {
-                            vx = 0;
+                for row in 0..n as usize {
+                    let mut py = start_y + row;
+                    if py >= HEIGHT {
+                        if self.quirks.clipping {
+                            break;
                         }
-                        let pixel_index: usize =
-                            ((vy as usize * WIDTH * 3) + ((vx as usize + b) * 3)) as usize;
-                        let old_pixel = self.display.get_pixel_byte(pixel_index);
-                        let new_pixel = sprite_row & (0b1000_0000 >> b);
-                        let new_pixel = if new_pixel != 0 { 0xFF } else { 0x00 };
-                        let new_pixel = new_pixel ^ old_pixel;
-                        self.display.set_pixel_byte(pixel_index as usize, new_pixel);
-                        self.display.set_pixel_byte(pixel_index + 1, new_pixel);
-                        self.display.set_pixel_byte(pixel_index + 2, new_pixel);
-                        vf_changed = vf_changed || (old_pixel & new_pixel) != old_pixel;
+                        py %= HEIGHT;
                     }
-                    vy += 1;
+                    let sprite_row = self.memory.read(self.i as usize + row);
+                    vf_changed |=
+                        self.framebuffer
+                            .draw_row(start_x, py, sprite_row, !self.quirks.clipping);
                 }
                 self.v[0xF] = if vf_changed { 1 } else { 0 };
                 self.increment_pc();
-                self.display.draw();
-                Instruction::Display
-            }
-            0xE => match rhs {
-                0x9E => {
-                    let x = Self::get_rightmost_nibble(lhs);
-                    if self.keyboard.is_pressed(self.v[x as usize]) {
-                        self.skip_next_instruction();
-                    } else {
-                        self.increment_pc();
-                    }
-                    Instruction::SKP
-                }
-                0xA1 => {
-                    let x = Self::get_rightmost_nibble(lhs);
-                    if !self.keyboard.is_pressed(self.v[x as usize]) {
-                        self.skip_next_instruction();
-                    } else {
-                        self.increment_pc();
-                    }
-                    Instruction::SKNP
-                }
-                _ => {
-                    unimplemented!("Unimplemented E")
-                }
-            },
-            0xF => match rhs {
-                0x7 => {
-                    let x = Self::get_rightmost_nibble(lhs);
-                    self.v[x as usize] = self.dt;
-                    self.increment_pc();
-                    Instruction::Load
-                }
-                0xA => {
-                    self.waiting_key = true;
-                    return Instruction::Load;
-                }
-                0x15 => {
-                    let x = Self::get_rightmost_nibble(lhs);
-                    self.dt = self.v[x as usize];
-                    self.increment_pc();
-                    Instruction::Load
-                }
-                0x18 => {
-                    let x = Self::get_rightmost_nibble(lhs);
-                    self.st = self.v[x as usize];
-                    self.increment_pc();
-                    Instruction::Load
-                }
-                0x1E => {
-                    let x = Self::get_rightmost_nibble(lhs);
-                    self.i += self.v[x as usize] as u16;
-                    self.increment_pc();
-                    Instruction::Add
-                }
-                0x29 => {
-                    let x = Self::get_rightmost_nibble(lhs);
-                    self.i = self.v[x as usize] as u16 * 5;
+                self.display.draw(&self.framebuffer);
+            }
+            Opcode::Skp { x } => {
+                if self.keyboard.is_pressed(self.v[x as usize]) {
+                    self.skip_next_instruction();
+                } else {
                     self.increment_pc();
-                    Instruction::Load
                 }
-                0x33 => {
-                    let x = Self::get_rightmost_nibble(lhs);
-                    self.memory.memory[(self.i + 2) as usize] = self.v[x as usize] % 10;
-                    self.memory.memory[(self.i + 1) as usize] = (self.v[x as usize] / 10) % 10;
-                    self.memory.memory[self.i as usize] = (self.v[x as usize] / 100) % 10;
+            }
+            Opcode::Sknp { x } => {
+                if !self.keyboard.is_pressed(self.v[x as usize]) {
+                    self.skip_next_instruction();
+                } else {
                     self.increment_pc();
-                    Instruction::Load
                 }
-                0x55 => {
-                    let x = Self::get_rightmost_nibble(lhs);
-                    self.v
-                        .iter()
-                        .take(x as usize + 1)
-                        .enumerate()
-                        .for_each(|(i, n)| {
-                            self.memory.memory[i + self.i as usize] = *n;
-                        });
-                    self.increment_pc();
-                    Instruction::Load
+            }
+            Opcode::LdVxDt { x } => {
+                self.v[x as usize] = self.dt;
+                self.increment_pc();
+            }
+            Opcode::LdVxK { x } => {
+                self.key_wait = Some(KeyWait::Press {
+                    x: x as usize,
+                    keys: self.keyboard.keys(),
+                });
+            }
+            Opcode::LdDtVx { x } => {
+                self.dt = self.v[x as usize];
+                self.increment_pc();
+            }
+            Opcode::LdStVx { x } => {
+                self.st = self.v[x as usize];
+                self.increment_pc();
+            }
+            Opcode::AddIVx { x } => {
+                self.i = self.i.wrapping_add(self.v[x as usize] as u16);
+                self.increment_pc();
+            }
+            Opcode::LdFVx { x } => {
+                self.i = self.memory.font().small_glyph(self.v[x as usize]);
+                self.increment_pc();
+            }
+            Opcode::LdHfVx { x } => {
+                self.i = self.memory.font().big_glyph(self.v[x as usize]);
+                self.increment_pc();
+            }
+            Opcode::LdBVx { x } => {
+                let i = self.i as usize;
+                self.memory.write(i + 2, self.v[x as usize] % 10);
+                self.memory.write(i + 1, (self.v[x as usize] / 10) % 10);
+                self.memory.write(i, (self.v[x as usize] / 100) % 10);
+                self.increment_pc();
+            }
+            Opcode::LdIVx { x } => {
+                self.v
+                    .iter()
+                    .take(x as usize + 1)
+                    .enumerate()
+                    .for_each(|(i, n)| {
+                        self.memory.write(i + self.i as usize, *n);
+                    });
+                if self.quirks.memory_increment {
+                    self.i = self.i.wrapping_add(x as u16 + 1);
                 }
-                0x65 => {
-                    let x = Self::get_rightmost_nibble(lhs);
-                    self.memory
-                        .memory
-                        .iter()
-                        .skip(self.i as usize)
-                        .take(x as usize + 1)
-                        .enumerate()
-                        .for_each(|(index, n)| {
-                            self.v[index] = *n;
-                        });
-                    self.increment_pc();
-                    Instruction::Load
+                self.increment_pc();
+            }
+            Opcode::LdVxI { x } => {
+                for index in 0..=x as usize {
+                    self.v[index] = self.memory.read(self.i as usize + index);
                 }
-                _ => {
-                    unimplemented!("UNIMPLEMENTED OPCODE: {:X?}", op)
+                if self.quirks.memory_increment {
+                    self.i = self.i.wrapping_add(x as u16 + 1);
                 }
-            },
-            _ => {
-                unimplemented!("UNIMPLEMENTED OPCODE: {:X?}", op)
+                self.increment_pc();
+            }
+            Opcode::Sys { .. } => {
+                return Err(format!("{opcode} calls machine code, which can't run here"));
+            }
+            Opcode::ScrollDown { .. }
+            | Opcode::ScrollUp { .. }
+            | Opcode::ScrollRight
+            | Opcode::ScrollLeft
+            | Opcode::Exit
+            | Opcode::Low
+            | Opcode::High
+            | Opcode::SaveVxVy { .. }
+            | Opcode::LoadVxVy { .. }
+            | Opcode::LdILong
+            | Opcode::Audio
+            | Opcode::Plane { .. }
+            | Opcode::Pitch { .. }
+            | Opcode::LdRVx { .. }
+            | Opcode::LdVxR { .. } => {
+                return Err(format!("{opcode} needs {}", opcode.extension()));
             }
         }
+        Ok(())
     }
-    fn get_rightmost_nibble(n: u8) -> u8 {
-        n & 0x0F
-    }
-    fn get_leftmost_nibble(n: u8) -> u8 {
-        n >> 4
-    }
+    // The pc wraps like the address space, so a ROM running off the end doesn't panic
     fn increment_pc(&mut self) {
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
     fn skip_next_instruction(&mut self) {
-        self.pc += 4;
-    }
-    fn decrement_dt(&mut self) {
-        self.dt -= 1;
+        self.pc = self.pc.wrapping_add(4);
     }
-    fn decrement_st(&mut self) {
-        self.st -= 1;
+    fn tick_timers(&mut self) {
+        if self.dt > 0 {
+            self.dt -= 1;
+        }
         if self.st > 0 {
-            self.sink.play();
-        } else {
-            self.sink.pause();
+            self.st -= 1;
+        }
+    }
+    pub fn buzzer_on(&self) -> bool {
+        self.st > 0
+    }
+    // Silent until the frontend picks a sink
+    // Instructions per frame
+    pub fn set_tick_rate(&mut self, tick_rate: usize) {
+        self.tick_rate = tick_rate;
+    }
+    pub fn set_protection(&mut self, protection: Protection) {
+        self.protection = protection;
+    }
+    // Whether strict mode stopped the emulator with an error
+    pub fn failed(&self) -> bool {
+        self.halt == Some(Halt::Error)
+    }
+    pub fn set_screenshot_scale(&mut self, scale: usize) {
+        self.screenshot_scale = scale;
+    }
+    // Saves the screen as it is emulated, in the palette the display is currently using
+    pub fn screenshot(
+        &self,
+        path: Option<&Path>,
+        scale: usize,
+    ) -> Result<PathBuf, Box<dyn std::error::Error + 'static>> {
+        let path = path.map_or_else(
+            || screenshot::timestamped_path("screenshot", "png"),
+            Path::to_path_buf,
+        );
+        screenshot::save_png(&self.framebuffer, &self.display.palette(), scale, &path)?;
+        Ok(path)
+    }
+    fn print_screenshot(&mut self, path: Option<&Path>, scale: usize) {
+        match self.screenshot(path, scale) {
+            Ok(path) => {
+                // stdout may be carrying a --record=- video
+                eprintln!("Saved screenshot to {}", path.display());
+                self.display
+                    .show_message(format!("Saved {}", path.display()).as_str());
+            }
+            Err(e) => eprintln!("Couldn't save screenshot: {e}"),
+        }
+    }
+    pub fn set_record_scale(&mut self, scale: usize) {
+        self.record_scale = scale;
+    }
+    // Messages about recordings go to stderr, stdout may be carrying the y4m stream
+    pub fn start_recording(&mut self, recorder: Recorder) {
+        self.stop_recording();
+        self.recorder = Some(recorder);
+    }
+    pub fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            match recorder.finish() {
+                Ok(path) => {
+                    eprintln!("Saved recording to {}", path.display());
+                    self.display
+                        .show_message(format!("Saved {}", path.display()).as_str());
+                }
+                Err(e) => eprintln!("Couldn't save recording: {e}"),
+            }
+        }
+    }
+    fn toggle_recording(&mut self) {
+        if self.recorder.is_some() {
+            self.stop_recording();
+            return;
+        }
+        let path = screenshot::timestamped_path("recording", "gif");
+        // GIFs have no sound, so the tone doesn't matter
+        let tone = Tone::default();
+        match Recorder::create(&path, None, tone, self.display.palette(), self.record_scale) {
+            Ok(recorder) => {
+                eprintln!("Recording to {}", path.display());
+                self.display.show_message("Recording");
+                self.recorder = Some(recorder);
+            }
+            Err(e) => eprintln!("Couldn't start recording: {e}"),
         }
     }
+    // For the conformance tests, displays get the framebuffer with every draw
+    #[cfg(test)]
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::font::{Font, FontSet};
+    use crate::memory::Layout;
+    use crate::quirks::QuirksProfile;
+    use std::cell::RefCell;
+    use std::rc::Rc;
     struct FakeDisplay {}
     impl DisplayTrait for FakeDisplay {
-        fn draw(&mut self) -> bool {
+        fn draw(&mut self, _framebuffer: &Framebuffer) -> bool {
             true
         }
-        fn clear(&mut self) {}
-        fn get_pixels(&self) -> [u8; WIDTH * HEIGHT * 3] {
-            [0; WIDTH * HEIGHT * 3]
+    }
+    fn cpu() -> CPU<FakeDisplay> {
+        CPU::new(
+            Memory::new(),
+            FakeDisplay {},
+            Keyboard::new(),
+            Quirks::default(),
+        )
+    }
+    // Keeps what the CPU reported for every frame where the test can see it
+    struct FakeAudio(Rc<RefCell<Vec<bool>>>);
+    impl AudioSink for FakeAudio {
+        fn frame(&mut self, buzzer_on: bool) -> Result<(), Box<dyn std::error::Error + 'static>> {
+            self.0.borrow_mut().push(buzzer_on);
+            Ok(())
         }
-        fn set_pixels(&mut self, value: [u8; WIDTH * HEIGHT * 3]) {}
-        fn get_pixel_byte(&self, _index: usize) -> u8 {
-            0
+    }
+    #[test]
+    fn should_report_the_buzzer_once_per_frame() {
+        let mut cpu = cpu();
+        let frames = Rc::new(RefCell::new(Vec::new()));
+        let mut audio: Box<dyn AudioSink> = Box::new(FakeAudio(frames.clone()));
+        // Loops on a jump to itself
+        cpu.memory.memory[0x200..0x202].copy_from_slice(&[0x12, 0x00]);
+        cpu.st = 2;
+        for _ in 0..3 {
+            cpu.run_frame();
         }
-        fn set_pixel_byte(&mut self, _index: usize, value: u8) {}
+        assert!(frames.borrow().is_empty());
+        cpu.play_sound(&mut audio);
+        assert_eq!(*frames.borrow(), [true, true, false]);
+        cpu.play_sound(&mut audio);
+        assert_eq!(frames.borrow().len(), 3);
     }
-    fn cpu() -> CPU<FakeDisplay> {
-        CPU::new(Memory::new(), FakeDisplay {}, Keyboard::new())
+    #[test]
+    fn should_reset_registers_and_reload_rom() {
+        let mut cpu = cpu();
+        cpu.memory.load("roms/tests/logo.ch8").unwrap();
+        let rom = cpu.memory.memory.clone();
+        cpu.memory.memory[0x200] = 0x00;
+        cpu.v[0x3] = 0x42;
+        cpu.i = 0x300;
+        cpu.pc = 0x210;
+        cpu.sp = 2;
+        cpu.dt = 10;
+        cpu.framebuffer.draw_row(0, 0, 0xFF, false);
+        cpu.reset().unwrap();
+        assert_eq!(cpu.memory.memory, rom);
+        assert_eq!(cpu.v, [0; 16]);
+        assert_eq!((cpu.i, cpu.pc, cpu.sp, cpu.dt), (0, 0x200, 0, 0));
+        assert!(!cpu.framebuffer.is_lit(0, 0));
+    }
+    #[test]
+    fn should_start_and_reset_at_the_entry_point() {
+        let layout = Layout {
+            load_address: 0x600,
+            entry: 0x602,
+            overwrite_font: false,
+            memory_size: 0x1000,
+        };
+        let mut memory = Memory::new();
+        memory.set_layout(layout);
+        memory.load_bytes(&[0x00, 0xE0, 0x16, 0x02]).unwrap();
+        let mut cpu = CPU::new(memory, FakeDisplay {}, Keyboard::new(), Quirks::default());
+        assert_eq!(cpu.pc, 0x602);
+        cpu.pc = 0x600;
+        cpu.reset().unwrap();
+        assert_eq!(cpu.pc, 0x602);
+        assert_eq!(cpu.memory.memory[0x602..0x604], [0x16, 0x02]);
+    }
+    #[test]
+    fn should_stop_on_violations_as_strict_mode_says() {
+        // Writes V0 below the program, then adds 1 to it six times
+        let mut rom = vec![0xA1, 0x00, 0xF0, 0x55];
+        rom.extend([0x70, 0x01].repeat(6));
+        let cases = [
+            ("off", 6, false),
+            ("low-write=break", 0, false),
+            ("error", 0, true),
+        ];
+        for (protection, v0, failed) in cases {
+            let mut memory = Memory::new();
+            memory.load_bytes(&rom).unwrap();
+            let mut cpu = CPU::new(memory, FakeDisplay {}, Keyboard::new(), Quirks::default());
+            cpu.set_protection(protection.parse().unwrap());
+            cpu.run_frame();
+            assert_eq!(cpu.v[0], v0, "{protection}");
+            assert_eq!(cpu.failed(), failed, "{protection}");
+        }
+    }
+    #[test]
+    fn should_not_advance_a_frame_once_halted() {
+        // Writes V0 below the program, then adds 1 to it
+        let rom = [0xA1, 0x00, 0xF0, 0x55, 0x70, 0x01];
+        let mut memory = Memory::new();
+        memory.load_bytes(&rom).unwrap();
+        let mut cpu = CPU::new(memory, FakeDisplay {}, Keyboard::new(), Quirks::default());
+        cpu.set_protection("low-write=break".parse().unwrap());
+        cpu.handle_frontend_events(vec![FrontendEvent::FrameAdvance]);
+        assert_eq!(cpu.pc, 0x204);
+        assert!(cpu.paused && cpu.halt.is_none());
+        let mut memory = Memory::new();
+        memory.load_bytes(&rom).unwrap();
+        let mut cpu = CPU::new(memory, FakeDisplay {}, Keyboard::new(), Quirks::default());
+        cpu.set_protection("error".parse().unwrap());
+        cpu.run_frame();
+        assert!(cpu.failed());
+        let pc = cpu.pc;
+        cpu.handle_frontend_events(vec![FrontendEvent::FrameAdvance]);
+        assert_eq!(cpu.pc, pc);
+        assert!(cpu.failed());
+    }
+    #[test]
+    fn should_stop_with_an_error_on_opcodes_it_cannot_run() {
+        // An unknown opcode, and SUPER-CHIP's HIGH
+        for rom in [[0x80, 0x08], [0x00, 0xFF]] {
+            let mut memory = Memory::new();
+            memory.load_bytes(&rom).unwrap();
+            let mut cpu = CPU::new(memory, FakeDisplay {}, Keyboard::new(), Quirks::default());
+            cpu.run_frame();
+            assert!(cpu.failed(), "{rom:02X?}");
+            assert_eq!(cpu.pc, 0x200, "{rom:02X?}");
+        }
+    }
+    #[test]
+    fn should_stop_with_an_error_when_the_stack_runs_out() {
+        // RET with nothing on the stack, and a subroutine calling itself forever
+        for rom in [[0x00, 0xEE], [0x22, 0x00]] {
+            let mut memory = Memory::new();
+            memory.load_bytes(&rom).unwrap();
+            let mut cpu = CPU::new(memory, FakeDisplay {}, Keyboard::new(), Quirks::default());
+            cpu.set_tick_rate(16);
+            cpu.run_frame();
+            assert!(cpu.failed(), "{rom:02X?}");
+            assert_eq!(cpu.pc, 0x200, "{rom:02X?}");
+        }
+    }
+    #[test]
+    fn should_wrap_i_and_pc_instead_of_overflowing() {
+        let mut cpu = cpu();
+        cpu.i = 0xFFFF;
+        cpu.v[0] = 2;
+        cpu.execute(Opcode::decode(0xF01E).unwrap()).unwrap();
+        assert_eq!(cpu.i, 1);
+        cpu.quirks.memory_increment = true;
+        cpu.i = 0xFFFF;
+        cpu.execute(Opcode::decode(0xF055).unwrap()).unwrap();
+        assert_eq!(cpu.i, 0);
+        cpu.pc = 0xFFFE;
+        cpu.execute(Opcode::decode(0x3002).unwrap()).unwrap();
+        assert_eq!(cpu.pc, 0x0002);
     }
     #[test]
     // 00EE - RET
@@ -561,7 +1014,7 @@ mod tests {
         let mut cpu = cpu();
         cpu.sp = 0xf;
         cpu.memory.memory[0xE] = 0x2;
-        cpu.decode(0x0, 0xEE);
+        cpu.execute(Opcode::decode(0x00EE).unwrap()).unwrap();
         assert_eq!(cpu.pc, 0x2);
         assert_eq!(cpu.sp, 0xE);
     }
@@ -570,7 +1023,7 @@ mod tests {
     // 1nnn - JP addr
     fn should_set_pc_to_nnn() {
         let mut cpu = cpu();
-        cpu.decode(0x12, 0xAA);
+        cpu.execute(Opcode::decode(0x12AA).unwrap()).unwrap();
         assert_eq!(cpu.pc, 0x2AA);
     }
     #[test]
@@ -579,7 +1032,7 @@ mod tests {
         let mut cpu = cpu();
         cpu.sp = 1;
         cpu.pc = 0x200;
-        cpu.decode(0x21, 0xAA);
+        cpu.execute(Opcode::decode(0x21AA).unwrap()).unwrap();
         assert_eq!(cpu.memory.stack[1], 0x200);
         assert_eq!(cpu.sp, 2);
         assert_eq!(cpu.pc, 0x1AA);
@@ -590,7 +1043,7 @@ mod tests {
         let mut cpu = cpu();
         cpu.pc = 0x200;
         cpu.v[1] = 0xAA;
-        cpu.decode(0x31, 0xAA);
+        cpu.execute(Opcode::decode(0x31AA).unwrap()).unwrap();
         assert_eq!(cpu.pc, 0x204);
     }
 
@@ -600,11 +1053,11 @@ mod tests {
         let mut cpu = cpu();
         cpu.pc = 0x200;
         cpu.v[1] = 0xBA;
-        cpu.decode(0x41, 0xAA);
+        cpu.execute(Opcode::decode(0x41AA).unwrap()).unwrap();
         assert_eq!(cpu.pc, 0x204);
         cpu.pc = 0x200;
         cpu.v[1] = 0xAA;
-        cpu.decode(0x41, 0xAA);
+        cpu.execute(Opcode::decode(0x41AA).unwrap()).unwrap();
         assert_ne!(cpu.pc, 0x204);
     }
     #[test]
@@ -614,19 +1067,19 @@ mod tests {
         cpu.pc = 0x200;
         cpu.v[0x1] = 0xBA;
         cpu.v[0xA] = 0xBA;
-        cpu.decode(0x51, 0xA0);
+        cpu.execute(Opcode::decode(0x51A0).unwrap()).unwrap();
         assert_eq!(cpu.pc, 0x204);
         cpu.pc = 0x200;
         cpu.v[0x1] = 0xFA;
         cpu.v[0xA] = 0xBA;
-        cpu.decode(0x51, 0xA0);
+        cpu.execute(Opcode::decode(0x51A0).unwrap()).unwrap();
         assert_eq!(cpu.pc, 0x202);
     }
     #[test]
     // 6xkk - LD Vx, byte
     fn should_load_kk_in_vx() {
         let mut cpu = cpu();
-        cpu.decode(0x6A, 0x11);
+        cpu.execute(Opcode::decode(0x6A11).unwrap()).unwrap();
         assert_eq!(cpu.v[0xA], 0x11);
     }
     #[test]
@@ -634,7 +1087,7 @@ mod tests {
     fn should_add_vx_and_kk() {
         let mut cpu = cpu();
         let sum = cpu.v[0xA] + 0x1;
-        cpu.decode(0x7A, 0x1);
+        cpu.execute(Opcode::decode(0x7A01).unwrap()).unwrap();
         assert_eq!(cpu.v[0xA], sum);
     }
     #[test]
@@ -643,7 +1096,7 @@ mod tests {
         let mut cpu = cpu();
         cpu.v[0x1] = 1;
         cpu.v[0xA] = 34;
-        cpu.decode(0x8A, 0x10);
+        cpu.execute(Opcode::decode(0x8A10).unwrap()).unwrap();
         assert_eq!(cpu.v[0x1], cpu.v[0xA]);
     }
     #[test]
@@ -653,7 +1106,7 @@ mod tests {
         cpu.v[0xA] = 2;
         cpu.v[0x1] = 10;
         let result = cpu.v[0xA] | cpu.v[0x1];
-        cpu.decode(0x8A, 0x11);
+        cpu.execute(Opcode::decode(0x8A11).unwrap()).unwrap();
         assert_eq!(cpu.v[0xA], result);
     }
     #[test]
@@ -663,7 +1116,7 @@ mod tests {
         cpu.v[0xA] = 2;
         cpu.v[0x2] = 10;
         let result = cpu.v[0xA] & cpu.v[0x2];
-        cpu.decode(0x8A, 0x22);
+        cpu.execute(Opcode::decode(0x8A22).unwrap()).unwrap();
         assert_eq!(cpu.v[0xA], result);
     }
     #[test]
@@ -673,7 +1126,7 @@ mod tests {
         cpu.v[0xA] = 2;
         cpu.v[0x2] = 10;
         let result = cpu.v[0xA] ^ cpu.v[0x2];
-        cpu.decode(0x8A, 0x23);
+        cpu.execute(Opcode::decode(0x8A23).unwrap()).unwrap();
         assert_eq!(cpu.v[0xA], result);
     }
     #[test]
@@ -686,7 +1139,7 @@ mod tests {
         cpu.v[x] = 0xFF;
         cpu.v[y] = 0x01;
 
-        cpu.decode(0x8A, 0x24);
+        cpu.execute(Opcode::decode(0x8A24).unwrap()).unwrap();
 
         assert_eq!(cpu.v[0xF], 1);
         assert_eq!(cpu.v[x], 0x00);
@@ -701,7 +1154,7 @@ mod tests {
         cpu.v[x] = 0xFF;
         cpu.v[y] = 0x01;
 
-        cpu.decode(0x8A, 0x25);
+        cpu.execute(Opcode::decode(0x8A25).unwrap()).unwrap();
 
         assert_eq!(cpu.v[0xF], 1);
         assert_eq!(cpu.v[x], 0xFE);
@@ -714,7 +1167,7 @@ mod tests {
         let y = 0x2;
         cpu.v[x] = 1;
         cpu.v[y] = 2;
-        cpu.decode(0x8A, 0x26);
+        cpu.execute(Opcode::decode(0x8A26).unwrap()).unwrap();
         assert_eq!(cpu.v[0xF], 1);
         assert_eq!(cpu.v[x], 0);
     }
@@ -726,7 +1179,7 @@ mod tests {
         let y = 0x2;
         cpu.v[x] = 8;
         cpu.v[y] = 10;
-        cpu.decode(0x8A, 0x27);
+        cpu.execute(Opcode::decode(0x8A27).unwrap()).unwrap();
         assert_eq!(cpu.v[0xF], 1);
         assert_eq!(cpu.v[x], 2);
     }
@@ -736,7 +1189,7 @@ mod tests {
         let mut cpu = cpu();
         let x = 0xA;
         cpu.v[x] = 0x81;
-        cpu.decode(0x8A, 0x2E);
+        cpu.execute(Opcode::decode(0x8A2E).unwrap()).unwrap();
         assert_eq!(cpu.v[0xF], 1);
         assert_eq!(cpu.v[x], 0x02);
     }
@@ -749,21 +1202,21 @@ mod tests {
         cpu.pc = 0x200;
         cpu.v[x] = 123;
         cpu.v[y] = 42;
-        cpu.decode(0x9A, 0xB0);
+        cpu.execute(Opcode::decode(0x9AB0).unwrap()).unwrap();
         assert_eq!(cpu.pc, 0x204);
     }
     #[test]
     // Annn - LD I, addr
     fn should_load_nnn_in_i() {
         let mut cpu = cpu();
-        cpu.decode(0xAB, 0x22);
+        cpu.execute(Opcode::decode(0xAB22).unwrap()).unwrap();
         assert_eq!(cpu.i, 0xB22);
     }
     #[test]
     // Bnnn - JP V0, addr
     fn should_jump_to_nnn_plus_v0() {
         let mut cpu = cpu();
-        cpu.decode(0xB1, 0x42);
+        cpu.execute(Opcode::decode(0xB142).unwrap()).unwrap();
         assert_eq!(cpu.pc, 0x142);
     }
     #[test]
@@ -772,7 +1225,7 @@ mod tests {
         let mut cpu = cpu();
         cpu.v[6] = 1;
         cpu.keyboard.press(1);
-        cpu.decode(0xE6, 0x9E);
+        cpu.execute(Opcode::decode(0xE69E).unwrap()).unwrap();
         assert_eq!(cpu.pc, 0x204);
     }
     #[test]
@@ -781,7 +1234,42 @@ mod tests {
         let mut cpu = cpu();
         cpu.v[6] = 1;
         cpu.keyboard.press(1);
-        cpu.decode(0xE6, 0xA1);
+        cpu.execute(Opcode::decode(0xE6A1).unwrap()).unwrap();
+        assert_eq!(cpu.pc, 0x202);
+    }
+    #[test]
+    // Fx0A - LD Vx, K
+    fn should_store_the_key_pressed_during_fx0a() {
+        let mut cpu = cpu();
+        cpu.memory.memory[0x200..0x202].copy_from_slice(&[0xF3, 0x0A]);
+        cpu.dt = 10;
+        cpu.run_frame();
+        assert_eq!(cpu.pc, 0x200);
+        assert_eq!(cpu.dt, 9);
+        cpu.keyboard.press(0xB);
+        cpu.cycle();
+        assert_eq!(cpu.v[3], 0xB);
+        assert_eq!(cpu.pc, 0x202);
+    }
+    #[test]
+    // Fx0A - LD Vx, K
+    fn should_wait_for_release_with_the_vip_quirk() {
+        let mut cpu = cpu();
+        cpu.quirks = Quirks::new(QuirksProfile::Vip);
+        cpu.memory.memory[0x200..0x202].copy_from_slice(&[0xF5, 0x0A]);
+        // Held before Fx0A ran, so it doesn't count until pressed again
+        cpu.keyboard.press(0x1);
+        cpu.cycle();
+        cpu.keyboard.press(0x7);
+        cpu.cycle();
+        cpu.cycle();
+        assert_eq!(cpu.pc, 0x200);
+        cpu.keyboard.release(0x1);
+        cpu.cycle();
+        assert_eq!(cpu.pc, 0x200);
+        cpu.keyboard.release(0x7);
+        cpu.cycle();
+        assert_eq!(cpu.v[5], 0x7);
         assert_eq!(cpu.pc, 0x202);
     }
     #[test]
@@ -789,7 +1277,7 @@ mod tests {
     fn should_load_dt_in_vx() {
         let mut cpu = cpu();
         cpu.dt = 42;
-        cpu.decode(0xF1, 0x07);
+        cpu.execute(Opcode::decode(0xF107).unwrap()).unwrap();
         assert_eq!(cpu.v[1], 42);
     }
     #[test]
@@ -797,7 +1285,7 @@ mod tests {
     fn should_load_vx_in_dt() {
         let mut cpu = cpu();
         cpu.v[1] = 42;
-        cpu.decode(0xF1, 0x15);
+        cpu.execute(Opcode::decode(0xF115).unwrap()).unwrap();
         assert_eq!(cpu.dt, 42);
     }
     #[test]
@@ -805,7 +1293,7 @@ mod tests {
     fn should_load_vc_in_st() {
         let mut cpu = cpu();
         cpu.v[1] = 42;
-        cpu.decode(0xF1, 0x18);
+        cpu.execute(Opcode::decode(0xF118).unwrap()).unwrap();
         assert_eq!(cpu.st, 42);
     }
     #[test]
@@ -814,7 +1302,7 @@ mod tests {
         let mut cpu = cpu();
         cpu.v[1] = 42;
         cpu.i = 1;
-        cpu.decode(0xF1, 0x1E);
+        cpu.execute(Opcode::decode(0xF11E).unwrap()).unwrap();
         assert_eq!(cpu.i, 43);
     }
     #[test]
@@ -823,15 +1311,35 @@ mod tests {
         let mut cpu = cpu();
         cpu.v[5] = 2;
         cpu.memory.load_sprites();
-        cpu.decode(0xF5, 0x29);
+        cpu.execute(Opcode::decode(0xF529).unwrap()).unwrap();
         assert_eq!(cpu.i, 10);
     }
     #[test]
+    // Fx29 - LD F, Vx and Fx30 - LD HF, Vx
+    fn should_point_i_at_the_font_base() {
+        let mut memory = Memory::new();
+        let mut font = Font::new(FontSet::Vip);
+        font.set_base(0x50).unwrap();
+        memory.set_font(font);
+        memory.load_bytes(&[0x00, 0xE0]).unwrap();
+        let mut cpu = CPU::new(memory, FakeDisplay {}, Keyboard::new(), Quirks::default());
+        cpu.v[5] = 0x1;
+        cpu.execute(Opcode::decode(0xF529).unwrap()).unwrap();
+        assert_eq!(cpu.i, 0x55);
+        assert_eq!(
+            cpu.memory.memory[0x55..0x5A],
+            [0x60, 0x20, 0x20, 0x20, 0x70]
+        );
+        cpu.execute(Opcode::decode(0xF530).unwrap()).unwrap();
+        assert_eq!(cpu.i, 0xAA);
+        assert_eq!(cpu.memory.memory[0xAA..0xAC], [0x18, 0x38]);
+    }
+    #[test]
     // Fx33 - LD B, Vx
     fn should_store_bcd_representation_of_vx() {
         let mut cpu = cpu();
         cpu.v[5] = 152;
-        cpu.decode(0xF5, 0x33);
+        cpu.execute(Opcode::decode(0xF533).unwrap()).unwrap();
         assert_eq!(cpu.memory.memory[cpu.i as usize], 1);
         assert_eq!(cpu.memory.memory[cpu.i as usize + 1], 5);
         assert_eq!(cpu.memory.memory[cpu.i as usize + 2], 2);
@@ -844,7 +1352,7 @@ mod tests {
         cpu.v[1] = 2;
         cpu.v[2] = 3;
         cpu.i = 0x200;
-        cpu.decode(0xF2, 0x55);
+        cpu.execute(Opcode::decode(0xF255).unwrap()).unwrap();
         assert_eq!(cpu.memory.memory[cpu.i as usize], 1);
         assert_eq!(cpu.memory.memory[cpu.i as usize + 1], 2);
         assert_eq!(cpu.memory.memory[cpu.i as usize + 2], 3);
@@ -857,7 +1365,7 @@ mod tests {
         cpu.memory.memory[cpu.i as usize] = 2;
         cpu.memory.memory[cpu.i as usize + 1] = 3;
         cpu.memory.memory[cpu.i as usize + 2] = 4;
-        cpu.decode(0xF2, 0x65);
+        cpu.execute(Opcode::decode(0xF265).unwrap()).unwrap();
         assert_eq!(cpu.v[0], 2);
         assert_eq!(cpu.v[1], 3);
         assert_eq!(cpu.v[2], 4);
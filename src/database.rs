@@ -0,0 +1,224 @@
+use crate::controller::ControllerConfig;
+use crate::palette::{Colour, Palette};
+use crate::platform::Platform;
+use crate::quirks::{Quirks, QuirksProfile};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+// In the format of programs.json from the community CHIP-8 database, so its files can be
+// used as they are with --database
+const EMBEDDED: &str = include_str!("../roms/database.json");
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Program {
+    pub title: String,
+    pub description: Option<String>,
+    pub release: Option<String>,
+    pub authors: Vec<String>,
+    // Every known version of the program, keyed by SHA-1
+    pub roms: BTreeMap<String, Rom>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Rom {
+    pub file: Option<String>,
+    // The platforms it runs on, best first
+    pub platforms: Vec<String>,
+    // Quirks that differ from the platform's, by platform
+    pub quirky_platforms: BTreeMap<String, BTreeMap<String, bool>>,
+    // Instructions per frame
+    pub tickrate: Option<u32>,
+    pub colors: Option<Colors>,
+    // What the CHIP-8 keys do, like "up" or "a"
+    pub keys: BTreeMap<String, u8>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Colors {
+    // Background first, then the foreground and the colours of the other planes
+    pub pixels: Vec<String>,
+}
+
+// A program and the version of it that was looked up
+#[derive(Clone, Copy, Debug)]
+pub struct Entry<'a> {
+    pub program: &'a Program,
+    pub rom: &'a Rom,
+}
+
+// The database's platforms and the profile that runs them, anything else isn't supported yet
+fn profile(platform: &str) -> Option<QuirksProfile> {
+    match platform {
+        "originalChip8" | "hybridVIP" => Some(QuirksProfile::Vip),
+        "modernChip8" => Some(QuirksProfile::Cowgod),
+        "chip48" | "superchip1" | "superchip" => Some(QuirksProfile::Schip),
+        _ => None,
+    }
+}
+
+impl<'a> Entry<'a> {
+    // Where the first supported platform loads programs, and how much memory it has
+    pub fn platform(&self) -> Option<Platform> {
+        self.rom
+            .platforms
+            .iter()
+            .find_map(|platform| match platform.as_str() {
+                "xochip" => Some(Platform::Xochip),
+                platform => profile(platform).map(|_| Platform::Vip),
+            })
+    }
+    // The first supported platform, with the ROM's own quirks on top
+    pub fn quirks(&self) -> Option<(&'a str, Quirks)> {
+        let (platform, profile) =
+            self.rom.platforms.iter().find_map(|platform| {
+                profile(platform).map(|profile| (platform.as_str(), profile))
+            })?;
+        let mut quirks = Quirks::new(profile);
+        for (quirk, on) in self
+            .rom
+            .quirky_platforms
+            .get(platform)
+            .into_iter()
+            .flatten()
+        {
+            match quirk.as_str() {
+                "shift" => quirks.shift_uses_vy = !on,
+                "memoryLeaveIUnchanged" => quirks.memory_increment = !on,
+                "wrap" => quirks.clipping = !on,
+                "jump" => quirks.jump_uses_vx = *on,
+                "logic" => quirks.vf_reset = *on,
+                _ => {}
+            }
+        }
+        Some((platform, quirks))
+    }
+    pub fn palette(&self) -> Result<Option<Palette>, String> {
+        let Some(colors) = &self.rom.colors else {
+            return Ok(None);
+        };
+        let [background, foreground, ..] = colors.pixels.as_slice() else {
+            return Ok(None);
+        };
+        let mut palette =
+            Palette::custom(Colour::from_str(foreground)?, Colour::from_str(background)?);
+        for (colour, pixel) in palette.colours.iter_mut().zip(&colors.pixels).skip(2) {
+            *colour = Colour::from_str(pixel)?;
+        }
+        Ok(Some(palette))
+    }
+    // The key hints put the directions on the d-pad and the actions on A and B
+    pub fn controller(&self) -> ControllerConfig {
+        let mut controller = ControllerConfig::default();
+        for (hint, key) in &self.rom.keys {
+            let button = match hint.as_str() {
+                "up" => "dpup",
+                "down" => "dpdown",
+                "left" => "dpleft",
+                "right" => "dpright",
+                "a" => "a",
+                "b" => "b",
+                _ => continue,
+            };
+            controller
+                .buttons
+                .entry(format!("{key:X}"))
+                .or_default()
+                .push(button.to_string());
+        }
+        controller
+    }
+}
+
+pub struct Database {
+    programs: Vec<Program>,
+}
+
+impl Database {
+    pub fn embedded() -> Self {
+        Self {
+            programs: serde_json::from_str(EMBEDDED).expect("The embedded database is invalid"),
+        }
+    }
+    // Programs in a local file come before the ones already there, so they can replace them
+    pub fn extend(&mut self, path: &Path) -> Result<(), String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("{e}: {}", path.display()))?;
+        let programs: Vec<Program> =
+            serde_json::from_str(&contents).map_err(|e| format!("{}: {e}", path.display()))?;
+        self.programs.splice(0..0, programs);
+        Ok(())
+    }
+    pub fn find(&self, sha1: &str) -> Option<Entry<'_>> {
+        self.programs.iter().find_map(|program| {
+            program
+                .roms
+                .iter()
+                .find(|(hash, _)| hash.eq_ignore_ascii_case(sha1))
+                .map(|(_, rom)| Entry { program, rom })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    const PROGRAMS: &str = r##"[{
+        "title": "Quirky",
+        "origin": { "type": "manual" },
+        "roms": {
+            "ABCDEF": {
+                "file": "quirky.ch8",
+                "platforms": ["xochip", "superchip"],
+                "quirkyPlatforms": { "superchip": { "wrap": true, "vblank": true } },
+                "tickrate": 30,
+                "colors": { "pixels": ["#000011", "#EEEEEE", "#AA0000"], "buzzer": "#990000" },
+                "keys": { "left": 7, "right": 8, "a": 6, "b": 6, "player2Up": 1 },
+                "screenRotation": 0
+            }
+        }
+    }]"##;
+    #[test]
+    fn should_read_community_database_entries() {
+        let database = Database {
+            programs: serde_json::from_str(PROGRAMS).unwrap(),
+        };
+        let entry = database.find("abcdef").unwrap();
+        assert_eq!(entry.program.title, "Quirky");
+        assert_eq!(entry.rom.tickrate, Some(30));
+        let (platform, quirks) = entry.quirks().unwrap();
+        assert_eq!(platform, "superchip");
+        assert!(quirks.jump_uses_vx && !quirks.clipping);
+        assert_eq!(entry.platform(), Some(Platform::Xochip));
+        let palette = entry.palette().unwrap().unwrap();
+        assert_eq!(palette.background(), Colour::from_rgb(0x000011));
+        assert_eq!(palette.foreground(), Colour::from_rgb(0xEEEEEE));
+        assert_eq!(palette.colours[2], Colour::from_rgb(0xAA0000));
+        let controller = entry.controller();
+        assert_eq!(controller.buttons["6"], ["a", "b"]);
+        assert_eq!(controller.buttons["7"], ["dpleft"]);
+        assert_eq!(controller.buttons.len(), 3);
+        assert!(database.find("123456").is_none());
+    }
+    #[test]
+    fn should_put_local_entries_first() {
+        let mut database = Database::embedded();
+        let pong = sha1_smol::Sha1::from(fs::read("roms/pong.ch8").unwrap())
+            .digest()
+            .to_string();
+        assert_eq!(database.find(&pong).unwrap().program.title, "Pong");
+        let path = env::temp_dir().join("chip-8-emulator-database-test.json");
+        let local = format!(r#"[{{ "title": "My Pong", "roms": {{ "{pong}": {{}} }} }}]"#);
+        fs::write(&path, local).unwrap();
+        database.extend(&path).unwrap();
+        let entry = database.find(&pong).unwrap();
+        assert_eq!(entry.program.title, "My Pong");
+        assert!(entry.quirks().is_none());
+        let _ = fs::remove_file(path);
+    }
+}
@@ -1,10 +1,11 @@
+use crate::memory::MAX_MEMORY_SIZE;
 use std::str::FromStr;
 
 pub const HELP_MESSAGE: &str = r#"
 Debugger Commands Help
 
 General Commands:
-- `step`: Execute a single CPU cycle and print the current cycle state.
+- `step`: Execute a single CPU cycle and print the instruction it ran, like `6A11 LD VA, 0x11`.
   Example: step
 
 - `run`: Run the emulator continuously at 500 Hz (2 ms per cycle). Exit the loop as if you were closing the window.
@@ -16,6 +17,9 @@ General Commands:
 - `help`: Display this help message.
   Example: help
 
+- `screenshot [file] [scale]`: Save the screen as a PNG, by default to screenshot-<time>.png at the --screenshot-scale size.
+  Example: screenshot pong.png 4
+
 Show Commands:
 The `show` command is used to display the value of specific registers, memory locations, or other components.
 Usage: show <arg>
@@ -74,8 +78,8 @@ impl FromStr for ShowArgs {
             input if input.starts_with("mem") => {
                 if let Some(address) = input.strip_prefix("mem").map(|s| s.trim()) {
                     match usize::from_str_radix(address, 16) {
-                        Ok(addr) if addr <= 0x1000 => Ok(Self::Mem(addr)),
-                        Ok(_) => Err("Memory address must be between 0 and 0x1000".to_string()),
+                        Ok(addr) if addr < MAX_MEMORY_SIZE => Ok(Self::Mem(addr)),
+                        Ok(_) => Err("Memory address must be between 0 and 0xFFFF".to_string()),
                         Err(_) => Err("Invalid Address".to_string()),
                     }
                 } else {
@@ -120,6 +124,7 @@ pub enum DebuggerAction {
     Run,
     Quit,
     Help,
+    Screenshot(Option<String>, Option<usize>),
 }
 
 impl FromStr for DebuggerAction {
@@ -142,6 +147,18 @@ impl FromStr for DebuggerAction {
             "run" => Ok(Self::Run),
             "quit" => Ok(Self::Quit),
             "help" => Ok(Self::Help),
+            input if input.starts_with("screenshot") => {
+                let mut args = input.trim_start_matches("screenshot").split_whitespace();
+                let path = args.next().map(|path| path.to_string());
+                let scale = match args.next() {
+                    Some(scale) => match scale.parse::<usize>() {
+                        Ok(scale) if scale >= 1 => Some(scale),
+                        _ => return Err(format!("Invalid scale: {scale}")),
+                    },
+                    None => None,
+                };
+                Ok(Self::Screenshot(path, scale))
+            }
             _ => Err(format!("No such arg: {input}").to_string()),
         }
     }
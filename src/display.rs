@@ -1,77 +1,448 @@
-use sdl2::pixels::PixelFormatEnum;
-use sdl2::render::Canvas;
-use sdl2::video::{Window, WindowContext};
+use crate::cpu::SpeedMode;
+use crate::framebuffer::Framebuffer;
+use crate::input::FrontendEvent;
+use crate::osd::{self, Osd};
+use crate::palette::{Colour, Palette};
+use crate::touchpad::{self, TouchKeypad};
+use clap::ValueEnum;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{FullscreenType, Window, WindowContext};
 use sdl2::Sdl;
-use sdl2::render::TextureCreator;
 use std::time::Instant;
 
 pub const WIDTH: usize = 64;
 pub const HEIGHT: usize = 32;
 const FPS: u64 = 60;
+// A pixel that goes dark keeps half of its brightness every frame
+const GHOST_DECAY: u16 = 128;
+// The OSD is drawn in whole window pixels, roughly this many OSD pixels high
+const OSD_HEIGHT: u32 = 128;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PresentMode {
+    // Present on every Dxyn and 00E0, like the original hardware
+    #[default]
+    Immediate,
+    // Present once at the end of each 60 Hz frame, hiding mid-frame XOR flicker
+    Frame,
+}
+
+impl PresentMode {
+    pub fn toggle(self) -> Self {
+        match self {
+            Self::Immediate => Self::Frame,
+            Self::Frame => Self::Immediate,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Scaling {
+    // Whole multiples of 64x32 only, so every CHIP-8 pixel has the same size
+    #[default]
+    Integer,
+    // As large as the window allows, still nearest-neighbour
+    Fit,
+}
+
+pub struct DisplayOptions {
+    pub title: String,
+    pub palette: Palette,
+    pub present_mode: PresentMode,
+    pub ghosting: bool,
+    pub scale: u32,
+    pub scaling: Scaling,
+    pub show_stats: bool,
+    pub show_keypad: bool,
+    pub touch_keypad: Option<TouchKeypad>,
+}
+
+// What the frontend shows next to the title
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Status {
+    pub speed: f32,
+    pub fps: f32,
+    pub ips: f32,
+    pub speed_mode: SpeedMode,
+    pub paused: bool,
+}
+
+impl Status {
+    pub fn title(&self, title: &str) -> String {
+        let mut status = format!("{title} - {:.0}%", self.speed * 100.0);
+        match self.speed_mode {
+            SpeedMode::Normal => {}
+            SpeedMode::Turbo => status.push_str(" - Turbo"),
+            SpeedMode::SlowMotion => status.push_str(" - Slow motion"),
+        }
+        if self.paused {
+            status.push_str(" - Paused");
+        }
+        status
+    }
+}
 
 pub trait DisplayTrait {
-    fn draw(&mut self) -> bool;
-    fn clear(&mut self);
-    fn get_pixels(&self) -> [u8; WIDTH * HEIGHT * 3];
-    fn set_pixels(&mut self, value: [u8; WIDTH * HEIGHT * 3]);
-    fn get_pixel_byte(&self, index: usize) -> u8;
-    fn set_pixel_byte(&mut self, index: usize, value: u8);
+    fn draw(&mut self, framebuffer: &Framebuffer) -> bool;
+    // Called once per emulated 60 Hz frame, after the timers tick
+    fn end_frame(&mut self, _framebuffer: &Framebuffer) {}
+    fn handle_event(&mut self, _event: FrontendEvent) {}
+    fn show_status(&mut self, _status: Status) {}
+    fn show_message(&mut self, _message: &str) {}
+    // Which of the 16 keys are held, once per frame
+    fn show_keys(&mut self, _keys: [bool; 16]) {}
+    fn palette(&self) -> Palette {
+        Palette::default()
+    }
+}
+
+// Draws nothing, for tests and headless runs
+pub struct HeadlessDisplay {
+    palette: Palette,
+}
+
+impl HeadlessDisplay {
+    pub fn new(palette: Palette) -> Self {
+        Self { palette }
+    }
 }
 
-pub struct Display {
+impl DisplayTrait for HeadlessDisplay {
+    fn draw(&mut self, _framebuffer: &Framebuffer) -> bool {
+        true
+    }
+    fn palette(&self) -> Palette {
+        self.palette.clone()
+    }
+}
+
+pub struct Display<'a> {
     pub title: String,
     pixels: [u8; WIDTH * HEIGHT * 3],
     canvas: Canvas<Window>,
+    texture: Texture<'a>,
     pub last_updated: Instant,
-    texture_creator: TextureCreator<WindowContext>
+    palette: Palette,
+    present_mode: PresentMode,
+    ghosting: bool,
+    brightness: [u8; WIDTH * HEIGHT],
+    scaling: Scaling,
+    osd: Osd,
+    osd_drawn: bool,
+    touch_keypad: Option<TouchKeypad>,
+    keys: [bool; 16],
 }
 
-impl Display {
-    pub fn new(sdl_context: &Sdl) -> Self {
-        let title = "CHIP-8 Emulator by luizf".to_string();
+impl<'a> Display<'a> {
+    // The window comes first so the caller can keep its texture creator, which the
+    // texture reused across frames borrows from
+    pub fn canvas(sdl_context: &Sdl, options: &DisplayOptions) -> Canvas<Window> {
         let video_subsystem = sdl_context.video().unwrap();
+        let (width, height) = touchpad::window_size(options.scale, options.touch_keypad);
         let window = video_subsystem
-            .window(title.as_str(), WIDTH as u32 * 10, HEIGHT as u32 * 10)
+            .window(options.title.as_str(), width, height)
             .position_centered()
+            .resizable()
             .build()
             .unwrap();
-        let canvas = window.into_canvas().build().unwrap();
-        let texture_creator = canvas.texture_creator();
+        window.into_canvas().build().unwrap()
+    }
+    pub fn new(
+        canvas: Canvas<Window>,
+        texture_creator: &'a TextureCreator<WindowContext>,
+        options: DisplayOptions,
+    ) -> Self {
+        let texture = texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGB24, WIDTH as u32, HEIGHT as u32)
+            .expect("Couldn't create texture");
         Self {
-            title,
+            title: options.title,
             pixels: [0; WIDTH * HEIGHT * 3],
             canvas,
+            texture,
             last_updated: Instant::now(),
-            texture_creator
+            palette: options.palette,
+            present_mode: options.present_mode,
+            ghosting: options.ghosting,
+            brightness: [0; WIDTH * HEIGHT],
+            scaling: options.scaling,
+            osd: Osd::new(options.show_stats, options.show_keypad),
+            osd_drawn: false,
+            touch_keypad: options.touch_keypad,
+            keys: [false; 16],
         }
     }
-}
-
-impl DisplayTrait for Display {
-    fn draw(&mut self) -> bool {
+    fn present(&mut self, framebuffer: &Framebuffer) {
         let now = Instant::now();
-        let mut texture = self.texture_creator
-            .create_texture_streaming(PixelFormatEnum::RGB24, WIDTH as u32, HEIGHT as u32)
-            .expect("Couldn't create texture");
-        let _ = texture.update(None, &self.pixels, WIDTH * 3);
-        let _ = self.canvas.copy(&texture, None, None);
+        let foreground = self.palette.foreground();
+        let background = self.palette.background();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let colour = if framebuffer.is_lit(x, y) {
+                    foreground
+                } else if self.ghosting {
+                    blend(background, foreground, self.brightness[y * WIDTH + x])
+                } else {
+                    background
+                };
+                let index = (y * WIDTH + x) * 3;
+                self.pixels[index..index + 3].copy_from_slice(&colour.to_array());
+            }
+        }
+        let _ = self.texture.update(None, &self.pixels, WIDTH * 3);
+        let output_size = self
+            .canvas
+            .output_size()
+            .unwrap_or((WIDTH as u32, HEIGHT as u32));
+        self.canvas.set_draw_color(Color::BLACK);
+        self.canvas.clear();
+        let viewport = match self.touch_keypad {
+            Some(touch_keypad) => {
+                let (screen, keypad) = touchpad::split(output_size, touch_keypad);
+                self.draw_touch_keypad(keypad);
+                letterbox(screen.size(), self.scaling)
+            }
+            None => letterbox(output_size, self.scaling),
+        };
+        let _ = self.canvas.copy(&self.texture, None, viewport);
+        self.draw_osd(viewport, now);
         self.canvas.present();
         self.last_updated = now;
-        return true;
     }
-    fn clear(&mut self) {
-        self.pixels = [0; WIDTH * HEIGHT * 3];
+    // On top of the scaled screen, with a shadow so it can be read over any palette
+    fn draw_osd(&mut self, viewport: Rect, now: Instant) {
+        let size = (viewport.height() / OSD_HEIGHT).max(1);
+        let pixels = self.osd.pixels(
+            (viewport.width() / size) as i32,
+            (viewport.height() / size) as i32,
+            now,
+        );
+        self.osd_drawn = !pixels.is_empty();
+        if pixels.is_empty() {
+            return;
+        }
+        let rects = |offset: i32| -> Vec<Rect> {
+            pixels
+                .iter()
+                .map(|(x, y)| {
+                    Rect::new(
+                        viewport.x() + x * size as i32 + offset,
+                        viewport.y() + y * size as i32 + offset,
+                        size,
+                        size,
+                    )
+                })
+                .collect()
+        };
+        self.canvas.set_draw_color(Color::BLACK);
+        let _ = self.canvas.fill_rects(&rects((size as i32 / 2).max(1)));
+        self.canvas.set_draw_color(Color::WHITE);
+        let _ = self.canvas.fill_rects(&rects(0));
+    }
+    // Outlined cells in the palette colours, held keys filled in
+    fn draw_touch_keypad(&mut self, keypad: Rect) {
+        let foreground = self.palette.foreground();
+        let background = self.palette.background();
+        let colour = |colour: Colour| Color::RGB(colour.r, colour.g, colour.b);
+        for (key, cell) in touchpad::cells(keypad) {
+            let held = self.keys[key as usize];
+            let inner = Rect::new(
+                cell.x() + 2,
+                cell.y() + 2,
+                cell.width().saturating_sub(4).max(1),
+                cell.height().saturating_sub(4).max(1),
+            );
+            let (fill, text) = if held {
+                (foreground, background)
+            } else {
+                (background, foreground)
+            };
+            self.canvas.set_draw_color(colour(foreground));
+            let _ = self.canvas.draw_rect(inner);
+            self.canvas.set_draw_color(colour(fill));
+            let _ = self.canvas.fill_rect(Rect::new(
+                inner.x() + 1,
+                inner.y() + 1,
+                inner.width().saturating_sub(2).max(1),
+                inner.height().saturating_sub(2).max(1),
+            ));
+            let Some((left, top, size)) = key_label(inner) else {
+                continue;
+            };
+            let mut pixels = Vec::new();
+            osd::text_pixels(format!("{key:X}").as_str(), 0, 0, &mut pixels);
+            let rects: Vec<Rect> = pixels
+                .iter()
+                .map(|(x, y)| Rect::new(left + x * size as i32, top + y * size as i32, size, size))
+                .collect();
+            self.canvas.set_draw_color(colour(text));
+            let _ = self.canvas.fill_rects(&rects);
+        }
+    }
+    fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let fullscreen = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        let _ = window.set_fullscreen(fullscreen);
     }
-    fn get_pixels(&self) -> [u8; WIDTH * HEIGHT * 3] {
-        self.pixels
+}
+
+// Largest 2:1 area that fits the window, centred with black bars around it
+fn letterbox((width, height): (u32, u32), scaling: Scaling) -> Rect {
+    let scale = (width as f32 / WIDTH as f32).min(height as f32 / HEIGHT as f32);
+    let scale = match scaling {
+        Scaling::Integer => scale.floor().max(1.0),
+        Scaling::Fit => scale,
+    };
+    let scaled_width = (WIDTH as f32 * scale) as u32;
+    let scaled_height = (HEIGHT as f32 * scale) as u32;
+    Rect::new(
+        (width as i32 - scaled_width as i32) / 2,
+        (height as i32 - scaled_height as i32) / 2,
+        scaled_width,
+        scaled_height,
+    )
+}
+
+fn blend(background: Colour, foreground: Colour, brightness: u8) -> Colour {
+    let channel = |from: u8, to: u8| {
+        (from as i32 + (to as i32 - from as i32) * brightness as i32 / 0xFF) as u8
+    };
+    Colour {
+        r: channel(background.r, foreground.r),
+        g: channel(background.g, foreground.g),
+        b: channel(background.b, foreground.b),
+    }
+}
+
+impl DisplayTrait for Display<'_> {
+    fn draw(&mut self, framebuffer: &Framebuffer) -> bool {
+        if self.present_mode == PresentMode::Frame {
+            return false;
+        }
+        self.present(framebuffer);
+        true
+    }
+    fn end_frame(&mut self, framebuffer: &Framebuffer) {
+        if self.ghosting {
+            for y in 0..HEIGHT {
+                for x in 0..WIDTH {
+                    let brightness = &mut self.brightness[y * WIDTH + x];
+                    *brightness = if framebuffer.is_lit(x, y) {
+                        0xFF
+                    } else {
+                        (*brightness as u16 * GHOST_DECAY / 0x100) as u8
+                    };
+                }
+            }
+        }
+        // Also present while the OSD is up, or right after it went away, so messages
+        // expire and counters update even when nothing is being drawn
+        if self.present_mode == PresentMode::Frame
+            || self.ghosting
+            || self.osd_drawn
+            || self.osd.is_visible(Instant::now())
+            || self.touch_keypad.is_some()
+        {
+            self.present(framebuffer);
+        }
+    }
+    fn handle_event(&mut self, event: FrontendEvent) {
+        match event {
+            FrontendEvent::CyclePalette => {
+                self.palette = self.palette.next();
+                self.show_message(format!("Palette {}", self.palette.name).as_str());
+            }
+            FrontendEvent::ToggleGhosting => {
+                self.ghosting = !self.ghosting;
+                self.brightness = [0; WIDTH * HEIGHT];
+                self.show_message(if self.ghosting {
+                    "Ghosting on"
+                } else {
+                    "Ghosting off"
+                });
+            }
+            FrontendEvent::TogglePresentMode => {
+                self.present_mode = self.present_mode.toggle();
+                self.show_message(match self.present_mode {
+                    PresentMode::Immediate => "Present on every draw",
+                    PresentMode::Frame => "Present once per frame",
+                });
+            }
+            FrontendEvent::ToggleFullscreen => self.toggle_fullscreen(),
+            FrontendEvent::ToggleStats => self.osd.show_stats = !self.osd.show_stats,
+            FrontendEvent::ToggleKeypadOverlay => self.osd.show_keypad = !self.osd.show_keypad,
+            _ => {}
+        }
+    }
+    fn show_status(&mut self, status: Status) {
+        let title = status.title(self.title.as_str());
+        let _ = self.canvas.window_mut().set_title(title.as_str());
+        self.osd.set_stats(status.fps, status.ips);
+    }
+    fn show_message(&mut self, message: &str) {
+        self.osd.message(message, Instant::now());
+    }
+    fn show_keys(&mut self, keys: [bool; 16]) {
+        self.osd.set_keys(keys);
+        self.keys = keys;
+    }
+    fn palette(&self) -> Palette {
+        self.palette.clone()
+    }
+}
+
+// Where the digit of a touch keypad cell goes and the size of its pixels, taking up half of the
+// cell height. Cells squeezed smaller than the digit are left without one
+fn key_label(inner: Rect) -> Option<(i32, i32, u32)> {
+    let size = (inner.height() / 2 / osd::GLYPH_HEIGHT as u32).max(1);
+    let width = size * osd::GLYPH_WIDTH as u32;
+    let height = size * osd::GLYPH_HEIGHT as u32;
+    if width > inner.width() || height > inner.height() {
+        return None;
+    }
+    let left = inner.x() + (inner.width().saturating_sub(width) / 2) as i32;
+    let top = inner.y() + (inner.height().saturating_sub(height) / 2) as i32;
+    Some((left, top, size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn should_letterbox_with_integer_scaling() {
+        let viewport = letterbox((700, 400), Scaling::Integer);
+        assert_eq!(viewport, Rect::new(30, 40, 640, 320));
     }
-    fn set_pixels(&mut self, value: [u8; WIDTH * HEIGHT * 3]) {
-        self.pixels = value;
+    #[test]
+    fn should_fill_window_width_when_fitting() {
+        let viewport = letterbox((700, 400), Scaling::Fit);
+        assert_eq!(viewport, Rect::new(0, 25, 700, 350));
     }
-    fn get_pixel_byte(&self, index: usize) -> u8 {
-        self.pixels[index]
+    #[test]
+    fn should_show_speed_and_paused_state_in_title() {
+        let status = Status {
+            speed: 2.0,
+            fps: 120.0,
+            ips: 960.0,
+            speed_mode: SpeedMode::Turbo,
+            paused: true,
+        };
+        assert_eq!(status.title("pong"), "pong - 200% - Turbo - Paused");
     }
-    fn set_pixel_byte(&mut self, index: usize, value: u8) {
-        self.pixels[index] = value;
+    #[test]
+    fn should_skip_labels_that_do_not_fit_the_key() {
+        let height = 4 * osd::GLYPH_HEIGHT as u32;
+        let label = key_label(Rect::new(10, 20, height, height));
+        let width = 2 * osd::GLYPH_WIDTH as u32;
+        assert_eq!(
+            label,
+            Some((10 + (height - width) as i32 / 2, 20 + height as i32 / 4, 2))
+        );
+        assert_eq!(key_label(Rect::new(0, 0, 1, 1)), None);
     }
 }
@@ -0,0 +1,172 @@
+use crate::memory::MAX_MEMORY_SIZE;
+use clap::ValueEnum;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+// 16 hex digits of 5 rows, for Fx29
+pub const SMALL_FONT_SIZE: usize = 80;
+// SUPER-CHIP 1.1 only has big glyphs for the digits 0 to 9, 10 rows each, for Fx30.
+// Custom fonts can bring all 16
+const BIG_GLYPH_SIZE: usize = 10;
+
+// What this emulator always used, from Cowgod's reference
+const COWGOD: [u8; SMALL_FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
+    0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
+    0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0,
+    0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0, 0xF0, 0x80, 0x80, 0x80,
+    0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
+];
+const VIP: [u8; SMALL_FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x60, 0x20, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
+    0x10, 0xF0, 0x10, 0xF0, 0xA0, 0xA0, 0xF0, 0x20, 0x20, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
+    0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x10, 0x10, 0x10, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0,
+    0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xF0, 0x50, 0x70, 0x50, 0xF0, 0xF0, 0x80, 0x80, 0x80,
+    0xF0, 0xF0, 0x50, 0x50, 0x50, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
+];
+const ETI_660: [u8; SMALL_FONT_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, 0x20, 0x20, 0x20, 0x20, 0x20, 0xE0, 0x20, 0xE0, 0x80, 0xE0, 0xE0,
+    0x20, 0xE0, 0x20, 0xE0, 0xA0, 0xA0, 0xE0, 0x20, 0x20, 0xE0, 0x80, 0xE0, 0x20, 0xE0, 0xE0, 0x80,
+    0xE0, 0xA0, 0xE0, 0xE0, 0x20, 0x20, 0x20, 0x20, 0xE0, 0xA0, 0xE0, 0xA0, 0xE0, 0xE0, 0xA0, 0xE0,
+    0x20, 0xE0, 0xE0, 0xA0, 0xE0, 0xA0, 0xA0, 0x80, 0x80, 0xE0, 0xA0, 0xE0, 0xE0, 0x80, 0x80, 0x80,
+    0xE0, 0x20, 0x20, 0xE0, 0xA0, 0xE0, 0xE0, 0x80, 0xE0, 0x80, 0xE0, 0xE0, 0x80, 0xC0, 0x80, 0x80,
+];
+const DREAM_6800: [u8; SMALL_FONT_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, 0x40, 0x40, 0x40, 0x40, 0x40, 0xE0, 0x20, 0xE0, 0x80, 0xE0, 0xE0,
+    0x20, 0xE0, 0x20, 0xE0, 0x80, 0xA0, 0xA0, 0xE0, 0x20, 0xE0, 0x80, 0xE0, 0x20, 0xE0, 0xE0, 0x80,
+    0xE0, 0xA0, 0xE0, 0xE0, 0x20, 0x20, 0x20, 0x20, 0xE0, 0xA0, 0xE0, 0xA0, 0xE0, 0xE0, 0xA0, 0xE0,
+    0x20, 0xE0, 0xE0, 0xA0, 0xE0, 0xA0, 0xA0, 0xC0, 0xA0, 0xE0, 0xA0, 0xC0, 0xE0, 0x80, 0x80, 0x80,
+    0xE0, 0xC0, 0xA0, 0xA0, 0xA0, 0xC0, 0xE0, 0x80, 0xE0, 0x80, 0xE0, 0xE0, 0x80, 0xC0, 0x80, 0x80,
+];
+const FISH_N_CHIPS: [u8; SMALL_FONT_SIZE] = [
+    0x60, 0xA0, 0xA0, 0xA0, 0xC0, 0x40, 0xC0, 0x40, 0x40, 0xE0, 0xC0, 0x20, 0x40, 0x80, 0xE0, 0xC0,
+    0x20, 0x40, 0x20, 0xC0, 0x20, 0xA0, 0xE0, 0x20, 0x20, 0xE0, 0x80, 0xC0, 0x20, 0xC0, 0x40, 0x80,
+    0xC0, 0xA0, 0x40, 0xE0, 0x20, 0x60, 0x40, 0x40, 0x40, 0xA0, 0x40, 0xA0, 0x40, 0x40, 0xA0, 0x60,
+    0x20, 0x40, 0x40, 0xA0, 0xE0, 0xA0, 0xA0, 0xC0, 0xA0, 0xC0, 0xA0, 0xC0, 0x60, 0x80, 0x80, 0x80,
+    0x60, 0xC0, 0xA0, 0xA0, 0xA0, 0xC0, 0xE0, 0x80, 0xC0, 0x80, 0xE0, 0xE0, 0x80, 0xC0, 0x80, 0x80,
+];
+const SCHIP_BIG: [u8; 10 * BIG_GLYPH_SIZE] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, 0x18, 0x38, 0x58, 0x18, 0x18, 0x18,
+    0x18, 0x18, 0x18, 0x3C, 0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, 0x3C, 0x7E,
+    0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, 0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF,
+    0x06, 0x06, 0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, 0x3E, 0x7C, 0xE0, 0xC0,
+    0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, 0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60,
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, 0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F,
+    0x03, 0x03, 0x3E, 0x7C,
+];
+
+// The small font of each machine, all of them get the SUPER-CHIP big font after it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum FontSet {
+    #[default]
+    Cowgod,
+    Vip,
+    Eti660,
+    Dream6800,
+    FishNChips,
+}
+
+// The small font followed by the big one, loaded into memory at `base`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Font {
+    glyphs: Vec<u8>,
+    base: u16,
+}
+
+impl Font {
+    pub fn new(set: FontSet) -> Self {
+        let small = match set {
+            FontSet::Cowgod => COWGOD,
+            FontSet::Vip => VIP,
+            FontSet::Eti660 => ETI_660,
+            FontSet::Dream6800 => DREAM_6800,
+            FontSet::FishNChips => FISH_N_CHIPS,
+        };
+        Self {
+            glyphs: [small.as_slice(), SCHIP_BIG.as_slice()].concat(),
+            base: 0,
+        }
+    }
+    // 80 bytes of small font, optionally followed by a big font for 10 or 16 digits
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let glyphs = fs::read(path).map_err(|e| format!("{e}: {}", path.display()))?;
+        let big = match glyphs.len().checked_sub(SMALL_FONT_SIZE) {
+            Some(0) => SCHIP_BIG.as_slice(),
+            Some(big) if big == 10 * BIG_GLYPH_SIZE || big == 16 * BIG_GLYPH_SIZE => &[],
+            _ => {
+                return Err(format!(
+                    "A font is {SMALL_FONT_SIZE} bytes, or {} or {} with a big font, not {}: {}",
+                    SMALL_FONT_SIZE + 10 * BIG_GLYPH_SIZE,
+                    SMALL_FONT_SIZE + 16 * BIG_GLYPH_SIZE,
+                    glyphs.len(),
+                    path.display()
+                ))
+            }
+        };
+        Ok(Self {
+            glyphs: [glyphs.as_slice(), big].concat(),
+            base: 0,
+        })
+    }
+    pub fn set_base(&mut self, base: u16) -> Result<(), String> {
+        if base as usize + self.glyphs.len() > MAX_MEMORY_SIZE {
+            return Err(format!(
+                "A {} byte font doesn't fit in memory at 0x{base:03X}",
+                self.glyphs.len()
+            ));
+        }
+        self.base = base;
+        Ok(())
+    }
+    pub fn glyphs(&self) -> &[u8] {
+        &self.glyphs
+    }
+    // Where the font sits in memory
+    pub fn range(&self) -> Range<usize> {
+        self.base as usize..self.base as usize + self.glyphs.len()
+    }
+    // Fx29 only looks at the low nibble of Vx, like the COSMAC VIP
+    pub fn small_glyph(&self, digit: u8) -> u16 {
+        self.base + (digit & 0xF) as u16 * 5
+    }
+    pub fn big_glyph(&self, digit: u8) -> u16 {
+        self.base + (SMALL_FONT_SIZE + (digit & 0xF) as usize * BIG_GLYPH_SIZE) as u16
+    }
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        Self::new(FontSet::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    #[test]
+    fn should_find_glyphs_from_the_base() {
+        let mut font = Font::new(FontSet::Vip);
+        font.set_base(0x50).unwrap();
+        assert_eq!(font.range(), 0x50..0x50 + 180);
+        assert_eq!(font.small_glyph(0x1), 0x55);
+        assert_eq!(font.small_glyph(0x1F), 0x9B);
+        assert_eq!(font.big_glyph(0x2), 0xA0 + 20);
+        assert_eq!(font.glyphs()[5..10], [0x60, 0x20, 0x20, 0x20, 0x70]);
+        assert!(font.set_base(0xFF50).is_err());
+    }
+    #[test]
+    fn should_load_a_font_from_a_file() {
+        let path = env::temp_dir().join("chip-8-emulator-font-test.bin");
+        fs::write(&path, [0xAA; SMALL_FONT_SIZE]).unwrap();
+        let font = Font::from_file(&path).unwrap();
+        assert_eq!(font.glyphs()[..SMALL_FONT_SIZE], [0xAA; SMALL_FONT_SIZE]);
+        assert_eq!(font.glyphs()[SMALL_FONT_SIZE..], SCHIP_BIG);
+        fs::write(&path, [0xBB; SMALL_FONT_SIZE + 160]).unwrap();
+        assert_eq!(Font::from_file(&path).unwrap().glyphs(), [0xBB; 240]);
+        fs::write(&path, [0xCC; 81]).unwrap();
+        assert!(Font::from_file(&path).is_err());
+        let _ = fs::remove_file(path);
+    }
+}
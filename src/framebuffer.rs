@@ -0,0 +1,101 @@
+use crate::display::HEIGHT;
+use crate::display::WIDTH;
+
+// One u64 per row, the leftmost pixel being the most significant bit
+#[derive(Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    rows: [u64; HEIGHT],
+}
+
+impl Framebuffer {
+    pub fn new() -> Self {
+        Self { rows: [0; HEIGHT] }
+    }
+    pub fn clear(&mut self) {
+        self.rows = [0; HEIGHT];
+    }
+    pub fn is_lit(&self, x: usize, y: usize) -> bool {
+        self.rows[y] & (1 << (WIDTH - 1 - x)) != 0
+    }
+    // XORs an 8 pixel wide sprite row at (x, y) and returns whether a lit pixel was turned off
+    pub fn draw_row(&mut self, x: usize, y: usize, sprite_row: u8, wrap: bool) -> bool {
+        let sprite_row = (sprite_row as u64) << (WIDTH - 8);
+        let mask = if wrap {
+            sprite_row.rotate_right(x as u32)
+        } else {
+            sprite_row >> x
+        };
+        let collision = self.rows[y] & mask != 0;
+        self.rows[y] ^= mask;
+        collision
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn should_xor_sprite_row_and_report_collision() {
+        let mut framebuffer = Framebuffer::new();
+        assert!(!framebuffer.draw_row(0, 0, 0b1100_0000, false));
+        assert!(framebuffer.is_lit(0, 0));
+        assert!(framebuffer.is_lit(1, 0));
+        assert!(framebuffer.draw_row(1, 0, 0b1000_0000, false));
+        assert!(framebuffer.is_lit(0, 0));
+        assert!(!framebuffer.is_lit(1, 0));
+    }
+    #[test]
+    fn should_wrap_sprite_row_around_right_edge() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.draw_row(60, 3, 0xFF, true);
+        assert!((60..64).chain(0..4).all(|x| framebuffer.is_lit(x, 3)));
+        assert!((4..60).all(|x| !framebuffer.is_lit(x, 3)));
+    }
+    #[test]
+    fn should_clip_sprite_row_at_right_edge() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.draw_row(60, 3, 0xFF, false);
+        assert!((60..64).all(|x| framebuffer.is_lit(x, 3)));
+        assert!((0..60).all(|x| !framebuffer.is_lit(x, 3)));
+    }
+    // The screen used to be a bool per pixel, drawn one pixel at a time
+    fn draw_row_per_pixel(
+        pixels: &mut [[bool; WIDTH]; HEIGHT],
+        x: usize,
+        y: usize,
+        sprite_row: u8,
+    ) -> bool {
+        let mut collision = false;
+        for bit in 0..8 {
+            if sprite_row & (0x80 >> bit) != 0 {
+                let pixel = &mut pixels[y][(x + bit) % WIDTH];
+                collision |= *pixel;
+                *pixel ^= true;
+            }
+        }
+        collision
+    }
+    #[test]
+    #[ignore = "a timing, run with cargo test --release -- --ignored --nocapture"]
+    fn should_draw_faster_than_a_bool_per_pixel() {
+        use std::hint::black_box;
+        use std::time::Instant;
+        const SPRITES: usize = 1_000_000;
+        let rows = || (0..SPRITES).map(|i| (i % WIDTH, i % HEIGHT, i as u8));
+        let start = Instant::now();
+        let mut pixels = [[false; WIDTH]; HEIGHT];
+        for (x, y, sprite_row) in rows() {
+            black_box(draw_row_per_pixel(&mut pixels, x, y, sprite_row));
+        }
+        let per_pixel = start.elapsed();
+        let start = Instant::now();
+        let mut framebuffer = Framebuffer::new();
+        for (x, y, sprite_row) in rows() {
+            black_box(framebuffer.draw_row(x, y, sprite_row, true));
+        }
+        let packed = start.elapsed();
+        println!("{SPRITES} sprite rows: {per_pixel:?} a bool per pixel, {packed:?} bit-packed");
+        assert!((0..HEIGHT).all(|y| (0..WIDTH).all(|x| pixels[y][x] == framebuffer.is_lit(x, y))));
+        assert!(packed < per_pixel);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::input::FrontendEvent;
+use std::collections::BTreeMap;
+
+// Name of the action in the config file, its default key and the event it sends.
+// Keys use SDL's names, which the terminal frontend maps its own keys to
+const DEFAULTS: [(&str, &str, FrontendEvent); 14] = [
+    ("palette", "F2", FrontendEvent::CyclePalette),
+    ("ghosting", "F3", FrontendEvent::ToggleGhosting),
+    ("present_mode", "F4", FrontendEvent::TogglePresentMode),
+    ("stats", "F5", FrontendEvent::ToggleStats),
+    ("keypad_overlay", "F6", FrontendEvent::ToggleKeypadOverlay),
+    ("mute", "F7", FrontendEvent::ToggleMute),
+    ("record", "F9", FrontendEvent::ToggleRecording),
+    ("fullscreen", "F11", FrontendEvent::ToggleFullscreen),
+    ("screenshot", "F12", FrontendEvent::Screenshot),
+    // Away from the letters, which the keypad presets use
+    ("pause", "F8", FrontendEvent::TogglePause),
+    ("frame_advance", "N", FrontendEvent::FrameAdvance),
+    ("reset", "Backspace", FrontendEvent::Reset),
+    ("turbo", "Tab", FrontendEvent::ToggleTurbo),
+    ("slow_motion", "M", FrontendEvent::ToggleSlowMotion),
+];
+
+pub struct Hotkeys {
+    // Key, action name and event
+    bindings: Vec<(String, &'static str, FrontendEvent)>,
+}
+
+impl Hotkeys {
+    // `overrides` maps action names to key names, `is_key` tells whether a key name exists
+    pub fn new(
+        overrides: &BTreeMap<String, String>,
+        is_key: impl Fn(&str) -> bool,
+    ) -> Result<Self, String> {
+        if let Some(action) = overrides
+            .keys()
+            .find(|action| !DEFAULTS.iter().any(|(name, _, _)| name == action))
+        {
+            return Err(format!(
+                "No such hotkey: {action}. Use one of {}",
+                DEFAULTS.map(|(name, _, _)| name).join(", ")
+            ));
+        }
+        let mut bindings = Vec::new();
+        for (action, key, event) in DEFAULTS {
+            let key = overrides.get(action).map_or(key, |key| key.as_str());
+            if !is_key(key) {
+                return Err(format!("No such key for {action}: {key}"));
+            }
+            bindings.push((key.to_string(), action, event));
+        }
+        Ok(Self { bindings })
+    }
+    pub fn event(&self, key: &str) -> Option<FrontendEvent> {
+        self.bindings
+            .iter()
+            .find(|(bound, _, _)| bound.eq_ignore_ascii_case(key))
+            .map(|(_, _, event)| *event)
+    }
+    // The config name of the action a key is bound to
+    pub fn action(&self, key: &str) -> Option<&'static str> {
+        self.bindings
+            .iter()
+            .find(|(bound, _, _)| bound.eq_ignore_ascii_case(key))
+            .map(|(_, action, _)| *action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn should_override_default_hotkeys() {
+        let overrides = BTreeMap::from([("pause".to_string(), "Space".to_string())]);
+        let hotkeys = Hotkeys::new(&overrides, |_| true).unwrap();
+        assert_eq!(hotkeys.event("space"), Some(FrontendEvent::TogglePause));
+        assert_eq!(hotkeys.event("F8"), None);
+        assert_eq!(hotkeys.action("SPACE"), Some("pause"));
+        assert_eq!(hotkeys.event("F2"), Some(FrontendEvent::CyclePalette));
+    }
+    #[test]
+    fn should_reject_unknown_actions_and_keys() {
+        let overrides = BTreeMap::from([("rewind".to_string(), "R".to_string())]);
+        assert!(Hotkeys::new(&overrides, |_| true).is_err());
+        let overrides = BTreeMap::from([("pause".to_string(), "Pouse".to_string())]);
+        assert!(Hotkeys::new(&overrides, |key| key != "Pouse").is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use crate::database::Entry;
+use crate::memory::Memory;
+use crate::opcode::{Extension, Opcode};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt;
+
+// What following every jump, call and skip from the entry point finds in the ROM
+pub struct Analysis {
+    // Whether each byte of the ROM is part of an instruction that can run
+    pub code: Vec<bool>,
+    pub opcodes: BTreeSet<(Extension, &'static str)>,
+}
+
+impl Analysis {
+    // Only an estimate, Bnnn lands somewhere after nnn that depends on V0, so only nnn
+    // is followed, and code that only runs after being written by the program isn't found
+    pub fn new(rom: &[u8], load_address: u16, entry: u16) -> Self {
+        let fetch = |address: usize| -> Option<u16> {
+            let offset = address.checked_sub(load_address as usize)?;
+            let bytes = rom.get(offset..offset + 2)?;
+            Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+        };
+        // XO-CHIP skips over the whole of F000 nnnn
+        let after = |address: usize| match fetch(address).map(Opcode::decode) {
+            Some(Ok(opcode)) => address + opcode.size(),
+            _ => address + 2,
+        };
+        let mut analysis = Self {
+            code: vec![false; rom.len()],
+            opcodes: BTreeSet::new(),
+        };
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![entry as usize];
+        while let Some(address) = pending.pop() {
+            if !visited.insert(address) {
+                continue;
+            }
+            let Some(Ok(opcode)) = fetch(address).map(Opcode::decode) else {
+                continue;
+            };
+            let offset = address - load_address as usize;
+            let end = (offset + opcode.size()).min(rom.len());
+            analysis.code[offset..end].fill(true);
+            analysis
+                .opcodes
+                .insert((opcode.extension(), opcode.pattern()));
+            let next = address + opcode.size();
+            match opcode {
+                Opcode::Ret | Opcode::Exit => {}
+                Opcode::Jp { nnn } | Opcode::JpV0 { nnn } => pending.push(nnn as usize),
+                Opcode::Call { nnn } => pending.extend([nnn as usize, next]),
+                Opcode::SeVxByte { .. }
+                | Opcode::SneVxByte { .. }
+                | Opcode::SeVxVy { .. }
+                | Opcode::SaveVxVy { .. }
+                | Opcode::LoadVxVy { .. }
+                | Opcode::SneVxVy { .. }
+                | Opcode::Skp { .. }
+                | Opcode::Sknp { .. } => pending.extend([next, after(next)]),
+                _ => pending.push(next),
+            }
+        }
+        analysis
+    }
+    pub fn code_bytes(&self) -> usize {
+        self.code.iter().filter(|code| **code).count()
+    }
+    // The smallest platform that runs every opcode found
+    pub fn needs(&self) -> Extension {
+        self.opcodes
+            .iter()
+            .map(|(extension, _)| *extension)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+// What the database knows about a ROM
+#[derive(Debug, Serialize)]
+pub struct Metadata {
+    pub title: String,
+    pub description: Option<String>,
+    pub release: Option<String>,
+    pub authors: Vec<String>,
+    pub platforms: Vec<String>,
+}
+
+// Everything `info` prints, as text or as JSON
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Info {
+    pub file: String,
+    pub size: usize,
+    pub sha1: String,
+    pub database: Option<Metadata>,
+    pub load_address: u16,
+    pub entry: u16,
+    pub opcodes: Vec<&'static str>,
+    pub platform: Extension,
+    pub code_bytes: usize,
+    pub data_bytes: usize,
+}
+
+impl Info {
+    pub fn new(file: &str, memory: &Memory, entry: Option<Entry<'_>>) -> Self {
+        let rom = memory.rom();
+        let analysis = Analysis::new(rom, memory.load_address(), memory.entry());
+        let code_bytes = analysis.code_bytes();
+        let mut opcodes: Vec<&'static str> = analysis
+            .opcodes
+            .iter()
+            .map(|(_, pattern)| *pattern)
+            .collect();
+        opcodes.sort_unstable();
+        Self {
+            file: file.to_string(),
+            size: rom.len(),
+            sha1: memory.rom_sha1(),
+            database: entry.map(|entry| Metadata {
+                title: entry.program.title.clone(),
+                description: entry.program.description.clone(),
+                release: entry.program.release.clone(),
+                authors: entry.program.authors.clone(),
+                platforms: entry.rom.platforms.clone(),
+            }),
+            load_address: memory.load_address(),
+            entry: memory.entry(),
+            opcodes,
+            platform: analysis.needs(),
+            code_bytes,
+            data_bytes: rom.len() - code_bytes,
+        }
+    }
+}
+
+impl fmt::Display for Info {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "File:      {}", self.file)?;
+        writeln!(f, "Size:      {} bytes", self.size)?;
+        writeln!(f, "SHA-1:     {}", self.sha1)?;
+        match &self.database {
+            Some(metadata) => {
+                writeln!(f, "Title:     {}", metadata.title)?;
+                if !metadata.authors.is_empty() {
+                    writeln!(f, "Authors:   {}", metadata.authors.join(", "))?;
+                }
+                if let Some(release) = &metadata.release {
+                    writeln!(f, "Released:  {release}")?;
+                }
+                if let Some(description) = &metadata.description {
+                    writeln!(f, "About:     {description}")?;
+                }
+                if !metadata.platforms.is_empty() {
+                    writeln!(f, "Runs on:   {}", metadata.platforms.join(", "))?;
+                }
+            }
+            None => writeln!(f, "Title:     not in the ROM database")?,
+        }
+        writeln!(f, "Platform:  {}, from the opcodes used", self.platform)?;
+        writeln!(f, "Opcodes:   {}", self.opcodes.join(" "))?;
+        writeln!(
+            f,
+            "Code:      {} bytes reachable from 0x{:03X}",
+            self.code_bytes, self.entry
+        )?;
+        write!(f, "Data:      {} bytes", self.data_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn should_follow_jumps_calls_and_skips() {
+        let rom = [
+            0x22, 0x08, // 0x200: call 0x208
+            0x30, 0x01, // 0x202: skip if V0 == 1
+            0x12, 0x0C, // 0x204: jump 0x20C
+            0x00, 0xFF, // 0x206: hires
+            0x60, 0x01, // 0x208: V0 = 1
+            0x00, 0xEE, // 0x20A: return
+            0x12, 0x0C, // 0x20C: jump 0x20C
+            0xF0, 0x0F, // 0x20E: data
+        ];
+        let analysis = Analysis::new(&rom, 0x200, 0x200);
+        assert_eq!(analysis.code_bytes(), 14);
+        assert!(!analysis.code[14] && !analysis.code[15]);
+        assert_eq!(analysis.needs(), Extension::Superchip);
+        assert!(analysis.opcodes.contains(&(Extension::Chip8, "2nnn")));
+        assert!(analysis.opcodes.contains(&(Extension::Superchip, "00FF")));
+    }
+    #[test]
+    fn should_skip_over_long_xo_chip_loads() {
+        let rom = [
+            0x40, 0x00, // 0x200: skip if V0 != 0
+            0xF0, 0x00, 0x12, 0x34, // 0x202: I = 0x1234
+            0x12, 0x06, // 0x206: jump 0x206
+        ];
+        let analysis = Analysis::new(&rom, 0x200, 0x200);
+        assert_eq!(analysis.code_bytes(), 8);
+        assert_eq!(analysis.needs(), Extension::Xochip);
+    }
+}
@@ -0,0 +1,440 @@
+use crate::controller::Controllers;
+use crate::hotkeys::Hotkeys;
+use crate::keyboard::Keyboard;
+use crate::keymap::Keymap;
+use crate::touchpad::Touchpad;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::{EventPump, Sdl};
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::net::{SocketAddr, TcpListener};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// Events meant for the frontend rather than for the CHIP-8 keypad
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrontendEvent {
+    Quit,
+    CyclePalette,
+    ToggleGhosting,
+    TogglePresentMode,
+    ToggleFullscreen,
+    Screenshot,
+    ToggleRecording,
+    ToggleStats,
+    ToggleKeypadOverlay,
+    TogglePause,
+    ToggleMute,
+    FrameAdvance,
+    Reset,
+    ToggleTurbo,
+    ToggleSlowMotion,
+    Redraw,
+}
+
+// Anything that holds CHIP-8 keys, polled once before every frame like the display is
+// drawn once after it. `frame` counts emulated frames, so sources can replay input exactly
+pub trait InputSource {
+    // Updates the keys this source holds and returns what it has for the frontend
+    fn poll(&mut self, frame: u64, keyboard: &mut Keyboard) -> Vec<FrontendEvent>;
+}
+
+// Several sources at once, a key is held while any of them holds it
+pub struct CombinedInput {
+    sources: Vec<(Box<dyn InputSource>, Keyboard)>,
+}
+
+impl CombinedInput {
+    pub fn new(sources: Vec<Box<dyn InputSource>>) -> Self {
+        Self {
+            sources: sources
+                .into_iter()
+                .map(|source| (source, Keyboard::new()))
+                .collect(),
+        }
+    }
+}
+
+impl InputSource for CombinedInput {
+    fn poll(&mut self, frame: u64, keyboard: &mut Keyboard) -> Vec<FrontendEvent> {
+        let mut frontend_events = Vec::new();
+        let mut combined = Keyboard::new();
+        for (source, keys) in self.sources.iter_mut() {
+            frontend_events.append(&mut source.poll(frame, keys));
+            combined = combined.union(keys);
+        }
+        *keyboard = combined;
+        frontend_events
+    }
+}
+
+pub struct SdlInput {
+    event_pump: EventPump,
+    hotkeys: Hotkeys,
+    keymap: Keymap,
+    controllers: Controllers,
+    touchpad: Touchpad,
+}
+
+impl SdlInput {
+    pub fn new(
+        sdl_context: &Sdl,
+        hotkeys: Hotkeys,
+        keymap: Keymap,
+        controllers: Controllers,
+        touchpad: Touchpad,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            event_pump: sdl_context.event_pump()?,
+            hotkeys,
+            keymap,
+            controllers,
+            touchpad,
+        })
+    }
+}
+
+impl InputSource for SdlInput {
+    fn poll(&mut self, _frame: u64, keyboard: &mut Keyboard) -> Vec<FrontendEvent> {
+        let mut frontend_events = Vec::new();
+        for event in self.event_pump.poll_iter() {
+            self.touchpad.handle_event(&event, keyboard);
+            match event {
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..) | WindowEvent::Exposed,
+                    ..
+                } => {
+                    frontend_events.push(FrontendEvent::Redraw);
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat,
+                    ..
+                } => match self.hotkeys.event(keycode.name().as_str()) {
+                    Some(event) => {
+                        if !repeat {
+                            frontend_events.push(event);
+                        }
+                    }
+                    None => {
+                        if let Some(key_num) = self.keymap.key(keycode.name().as_str()) {
+                            keyboard.press(key_num);
+                        }
+                    }
+                },
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    if let Some(key_num) = self.keymap.key(keycode.name().as_str()) {
+                        keyboard.release(key_num);
+                    }
+                }
+                Event::Quit { .. } => {
+                    frontend_events.push(FrontendEvent::Quit);
+                }
+                event => self.controllers.handle_event(&event, keyboard),
+            }
+        }
+        frontend_events
+    }
+}
+
+// One line of an input script or of the network protocol
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Wait(u64),
+    Press(Vec<u8>),
+    Release(Vec<u8>),
+    Quit,
+}
+
+fn parse_keys(keys: &[&str]) -> Result<Vec<u8>, String> {
+    if keys.is_empty() {
+        return Err("No keys given".to_string());
+    }
+    keys.iter()
+        .map(|key| {
+            u8::from_str_radix(key, 16)
+                .ok()
+                .filter(|key| *key < 16)
+                .ok_or(format!("No such CHIP-8 key: {key}, use 0 to F"))
+        })
+        .collect()
+}
+
+impl FromStr for Command {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = input.split_whitespace().collect();
+        match words.as_slice() {
+            ["wait", frames] => frames
+                .parse()
+                .map(Self::Wait)
+                .map_err(|_| format!("Invalid number of frames: {frames}")),
+            ["press", keys @ ..] => Ok(Self::Press(parse_keys(keys)?)),
+            ["release", keys @ ..] => Ok(Self::Release(parse_keys(keys)?)),
+            ["quit"] => Ok(Self::Quit),
+            _ => Err(format!(
+                "Unknown command: {}. Use wait <frames>, press <keys>, release <keys> or quit",
+                input.trim()
+            )),
+        }
+    }
+}
+
+// Applies a command that doesn't wait, returning the event it sends if any
+fn apply(command: &Command, keyboard: &mut Keyboard) -> Option<FrontendEvent> {
+    match command {
+        Command::Press(keys) => keys.iter().for_each(|key| keyboard.press(*key)),
+        Command::Release(keys) => keys.iter().for_each(|key| keyboard.release(*key)),
+        Command::Quit => return Some(FrontendEvent::Quit),
+        Command::Wait(_) => {}
+    }
+    None
+}
+
+// Lines that are empty or start with # are skipped
+fn parse_lines<T: FromStr<Err = String>>(contents: &str) -> Result<Vec<T>, String> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim().starts_with('#'))
+        .map(|(number, line)| {
+            line.parse()
+                .map_err(|e| format!("line {}: {e}", number + 1))
+        })
+        .collect()
+}
+
+fn read_file(path: &str) -> Result<String, Box<dyn std::error::Error + 'static>> {
+    Ok(fs::read_to_string(path).map_err(|e| format!("{e}: {path}"))?)
+}
+
+// Commands run in order from the first frame, `wait` lets that many frames go by
+pub struct ScriptInput {
+    commands: Vec<Command>,
+    next: usize,
+    resume_at: Option<u64>,
+}
+
+impl ScriptInput {
+    pub fn new(commands: Vec<Command>) -> Self {
+        Self {
+            commands,
+            next: 0,
+            resume_at: None,
+        }
+    }
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error + 'static>> {
+        let commands =
+            parse_lines(read_file(path)?.as_str()).map_err(|e| format!("{path}: {e}"))?;
+        Ok(Self::new(commands))
+    }
+}
+
+impl InputSource for ScriptInput {
+    fn poll(&mut self, frame: u64, keyboard: &mut Keyboard) -> Vec<FrontendEvent> {
+        let mut frontend_events = Vec::new();
+        while let Some(command) = self.commands.get(self.next) {
+            if let Command::Wait(frames) = command {
+                let resume_at = *self.resume_at.get_or_insert(frame + frames);
+                if frame < resume_at {
+                    break;
+                }
+                self.resume_at = None;
+            }
+            frontend_events.extend(apply(command, keyboard));
+            self.next += 1;
+        }
+        frontend_events
+    }
+}
+
+// The keys held from a frame on, until the next one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MovieFrame {
+    frame: u64,
+    keys: [bool; 16],
+}
+
+impl FromStr for MovieFrame {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (frame, keys) = input
+            .trim()
+            .split_once(' ')
+            .ok_or(format!("Expected <frame> <keys>: {}", input.trim()))?;
+        let frame = frame
+            .parse()
+            .map_err(|_| format!("Invalid frame: {frame}"))?;
+        let mut held = [false; 16];
+        let keys = keys.trim();
+        if keys != "-" {
+            let keys: Vec<String> = keys.chars().map(String::from).collect();
+            let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+            for key in parse_keys(&keys)? {
+                held[key as usize] = true;
+            }
+        }
+        Ok(Self { frame, keys: held })
+    }
+}
+
+// Replays the exact keys held on every frame, each line sets them from its frame on,
+// `120 5A` holds 5 and A from frame 120 and `130 -` lets go of everything
+pub struct MovieInput {
+    frames: Vec<MovieFrame>,
+}
+
+impl MovieInput {
+    pub fn new(mut frames: Vec<MovieFrame>) -> Self {
+        frames.sort_by_key(|movie_frame| movie_frame.frame);
+        Self { frames }
+    }
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error + 'static>> {
+        let frames = parse_lines(read_file(path)?.as_str()).map_err(|e| format!("{path}: {e}"))?;
+        Ok(Self::new(frames))
+    }
+}
+
+impl InputSource for MovieInput {
+    fn poll(&mut self, frame: u64, keyboard: &mut Keyboard) -> Vec<FrontendEvent> {
+        let keys = self
+            .frames
+            .iter()
+            .take_while(|movie_frame| movie_frame.frame <= frame)
+            .last()
+            .map_or([false; 16], |movie_frame| movie_frame.keys);
+        *keyboard = Keyboard::new();
+        for (key, held) in keys.iter().enumerate() {
+            if *held {
+                keyboard.press(key as u8);
+            }
+        }
+        Vec::new()
+    }
+}
+
+// Takes the script commands other than wait over TCP, one per line, from any number
+// of clients. Each connection is read on its own thread and commands apply on the next frame
+pub struct NetworkInput {
+    commands: Receiver<Command>,
+    address: SocketAddr,
+}
+
+impl NetworkInput {
+    pub fn listen(address: &str) -> Result<Self, Box<dyn std::error::Error + 'static>> {
+        let listener = TcpListener::bind(address).map_err(|e| format!("{e}: {address}"))?;
+        let address = listener.local_addr()?;
+        let (sender, commands) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    for line in BufReader::new(stream).lines() {
+                        let Ok(line) = line else {
+                            break;
+                        };
+                        match line.parse() {
+                            Ok(Command::Wait(_)) => eprintln!("Network input can't wait"),
+                            Ok(command) => {
+                                if sender.send(command).is_err() {
+                                    break;
+                                }
+                            }
+                            Err(e) => eprintln!("Network input: {e}"),
+                        }
+                    }
+                });
+            }
+        });
+        Ok(Self { commands, address })
+    }
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl InputSource for NetworkInput {
+    fn poll(&mut self, _frame: u64, keyboard: &mut Keyboard) -> Vec<FrontendEvent> {
+        self.commands
+            .try_iter()
+            .filter_map(|command| apply(&command, keyboard))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpStream;
+    use std::time::{Duration, Instant};
+    fn poll(source: &mut impl InputSource, frame: u64, keyboard: &mut Keyboard) -> bool {
+        source.poll(frame, keyboard).contains(&FrontendEvent::Quit)
+    }
+    #[test]
+    fn should_run_scripts_frame_by_frame() {
+        let commands =
+            parse_lines("# Start\npress 5 a\nwait 2\n\nrelease 5\nwait 1\nquit").unwrap();
+        let mut script = ScriptInput::new(commands);
+        let mut keyboard = Keyboard::new();
+        assert!(!poll(&mut script, 10, &mut keyboard));
+        assert!(keyboard.is_pressed(0x5) && keyboard.is_pressed(0xA));
+        assert!(!poll(&mut script, 11, &mut keyboard));
+        assert!(keyboard.is_pressed(0x5));
+        assert!(!poll(&mut script, 12, &mut keyboard));
+        assert!(!keyboard.is_pressed(0x5) && keyboard.is_pressed(0xA));
+        assert!(poll(&mut script, 13, &mut keyboard));
+        assert_eq!(
+            parse_lines::<Command>("press G"),
+            Err("line 1: No such CHIP-8 key: G, use 0 to F".to_string())
+        );
+    }
+    #[test]
+    fn should_replay_movies_by_frame() {
+        let frames = parse_lines("130 -\n120 5A\n").unwrap();
+        let mut movie = MovieInput::new(frames);
+        let mut keyboard = Keyboard::new();
+        keyboard.press(0x1);
+        poll(&mut movie, 0, &mut keyboard);
+        assert!(!keyboard.is_any_pressed());
+        poll(&mut movie, 125, &mut keyboard);
+        assert_eq!(keyboard.keys().iter().filter(|held| **held).count(), 2);
+        assert!(keyboard.is_pressed(0xA));
+        poll(&mut movie, 130, &mut keyboard);
+        assert!(!keyboard.is_any_pressed());
+    }
+    #[test]
+    fn should_hold_keys_from_any_combined_source() {
+        let human = ScriptInput::new(vec![Command::Press(vec![0x5])]);
+        let script = ScriptInput::new(vec![
+            Command::Press(vec![0x5, 0x6]),
+            Command::Wait(1),
+            Command::Release(vec![0x5, 0x6]),
+        ]);
+        let mut input = CombinedInput::new(vec![Box::new(human), Box::new(script)]);
+        let mut keyboard = Keyboard::new();
+        poll(&mut input, 0, &mut keyboard);
+        assert!(keyboard.is_pressed(0x5) && keyboard.is_pressed(0x6));
+        poll(&mut input, 1, &mut keyboard);
+        assert!(keyboard.is_pressed(0x5) && !keyboard.is_pressed(0x6));
+    }
+    #[test]
+    fn should_take_commands_over_the_network() {
+        let mut network = NetworkInput::listen("127.0.0.1:0").unwrap();
+        let mut stream = TcpStream::connect(network.address()).unwrap();
+        stream
+            .write_all(b"press 3 c\nrelease c\nwait 2\nquit\n")
+            .unwrap();
+        let mut keyboard = Keyboard::new();
+        let started = Instant::now();
+        while !poll(&mut network, 0, &mut keyboard) {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(keyboard.is_pressed(0x3) && !keyboard.is_pressed(0xC));
+    }
+}
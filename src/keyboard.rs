@@ -1,25 +1,6 @@
-use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
-use sdl2::{EventPump, Sdl};
 
-pub const KEYS: [Keycode; 16] = [
-    Keycode::X,
-    Keycode::Num1,
-    Keycode::Num2,
-    Keycode::Num3,
-    Keycode::Q,
-    Keycode::W,
-    Keycode::E,
-    Keycode::A,
-    Keycode::S,
-    Keycode::D,
-    Keycode::Z,
-    Keycode::C,
-    Keycode::Num4,
-    Keycode::R,
-    Keycode::F,
-    Keycode::V,
-];
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct Keyboard {
     keys_state: u16,
 }
@@ -40,40 +21,21 @@ impl Keyboard {
     pub fn release(&mut self, key: u8) {
         self.keys_state &= !(1 << key);
     }
-    pub fn update(&mut self, sdl_context: &Sdl) -> bool {
-        let mut event_pump = sdl_context.event_pump().unwrap();
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::KeyDown { keycode, .. } => {
-                    if let Some(key) = keycode {
-                        if let Some(key_num) = map_key_to_u8(key) {
-                            self.press(key_num);
-                        }
-                    }
-                }
-                Event::KeyUp { keycode, .. } => {
-                    if let Some(key) = keycode {
-                        if let Some(key_num) = map_key_to_u8(key) {
-                            self.release(key_num);
-                        }
-                    }
-                }
-                Event::Quit { .. } => {
-                    return true;
-                }
-                _ => {}
-            }
-        }
-        false
+    pub fn keys(&self) -> [bool; 16] {
+        std::array::from_fn(|key| self.is_pressed(key as u8))
     }
+    #[cfg(test)]
     pub fn is_any_pressed(&self) -> bool {
         self.keys_state != 0
     }
+    // The keys held on either keyboard
+    pub fn union(&self, other: &Keyboard) -> Keyboard {
+        Keyboard {
+            keys_state: self.keys_state | other.keys_state,
+        }
+    }
 }
 
-pub fn map_key_to_u8(key: Keycode) -> Option<u8> {
-    KEYS.iter()
-        .enumerate()
-        .find_map(|(i, k)| if *k == key { Some(i) } else { None })
-        .map(|i| i as u8)
+pub fn is_key_name(name: &str) -> bool {
+    Keycode::from_name(name).is_some()
 }
@@ -0,0 +1,254 @@
+use crate::display::DisplayTrait;
+use crate::framebuffer::Framebuffer;
+use crate::hotkeys::Hotkeys;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::Sdl;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+// The COSMAC VIP keypad, row by row
+const KEYPAD: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+// The host keys at the same place as the keypad keys above, on the left of each layout.
+// Keys use SDL's names, which depend on the layout the OS is set to
+const PRESETS: [(&str, [&str; 16]); 3] = [
+    (
+        "qwerty",
+        [
+            "1", "2", "3", "4", "Q", "W", "E", "R", "A", "S", "D", "F", "Z", "X", "C", "V",
+        ],
+    ),
+    (
+        "azerty",
+        [
+            "1", "2", "3", "4", "A", "Z", "E", "R", "Q", "S", "D", "F", "W", "X", "C", "V",
+        ],
+    ),
+    (
+        "dvorak",
+        [
+            "1", "2", "3", "4", "'", ",", ".", "P", "A", "O", "E", "U", ";", "Q", "J", "K",
+        ],
+    ),
+];
+
+// What a keymap file or a `[keypad]` section holds, CHIP-8 keys 0 to F to one or more host keys
+pub type KeypadConfig = BTreeMap<String, Vec<String>>;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct KeymapFile {
+    keypad: KeypadConfig,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keymap {
+    keys: [Vec<String>; 16],
+}
+
+impl Keymap {
+    pub fn preset(name: &str) -> Option<Self> {
+        PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, host_keys)| {
+                let mut keys: [Vec<String>; 16] = Default::default();
+                for (key, host_key) in KEYPAD.iter().zip(host_keys) {
+                    keys[*key as usize] = vec![host_key.to_string()];
+                }
+                Self { keys }
+            })
+    }
+    // Either a preset name or a keymap file, whose keys replace the ones of the QWERTY preset
+    pub fn from_arg(arg: &str) -> Result<Self, Box<dyn std::error::Error + 'static>> {
+        if let Some(keymap) = Self::preset(arg) {
+            return Ok(keymap);
+        }
+        let contents = fs::read_to_string(arg).map_err(|e| {
+            format!(
+                "{e}: {arg}. Use a keymap file or one of {}",
+                PRESETS.map(|(preset, _)| preset).join(", ")
+            )
+        })?;
+        let file: KeymapFile =
+            toml::from_str(contents.as_str()).map_err(|e| format!("{arg}: {e}"))?;
+        let mut keymap = Self::default();
+        keymap.apply(&file.keypad)?;
+        Ok(keymap)
+    }
+    // Nothing bound, for mappings that aren't keyboard layouts
+    pub fn empty() -> Self {
+        Self {
+            keys: Default::default(),
+        }
+    }
+    // Replaces the host keys of every CHIP-8 key in `keypad`, leaving the others alone.
+    // A host key moved to another CHIP-8 key no longer presses the one it was on
+    pub fn apply(&mut self, keypad: &KeypadConfig) -> Result<(), String> {
+        for (key, host_keys) in keypad {
+            let index = u8::from_str_radix(key, 16)
+                .ok()
+                .filter(|key| *key < 16)
+                .ok_or(format!("No such CHIP-8 key: {key}, use 0 to F"))?;
+            for bound in self.keys.iter_mut() {
+                bound.retain(|bound| {
+                    !host_keys
+                        .iter()
+                        .any(|host_key| host_key.eq_ignore_ascii_case(bound))
+                });
+            }
+            self.keys[index as usize] = host_keys.clone();
+        }
+        Ok(())
+    }
+    pub fn check(&self, is_key: impl Fn(&str) -> bool) -> Result<(), String> {
+        for (key, host_keys) in self.keys.iter().enumerate() {
+            if let Some(host_key) = host_keys.iter().find(|host_key| !is_key(host_key)) {
+                return Err(format!("No such key for {key:X}: {host_key}"));
+            }
+        }
+        Ok(())
+    }
+    // Hotkeys are looked up before the keypad, so a key bound to both would never reach it
+    pub fn check_hotkeys(&self, hotkeys: &Hotkeys) -> Result<(), String> {
+        for (key, host_keys) in self.keys.iter().enumerate() {
+            for host_key in host_keys {
+                if let Some(action) = hotkeys.action(host_key) {
+                    return Err(format!(
+                        "{host_key} is bound to {key:X} and to the {action} hotkey, move one of them"
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+    // The CHIP-8 key a host key is bound to, names are compared ignoring case
+    pub fn key(&self, host_key: &str) -> Option<u8> {
+        self.keys
+            .iter()
+            .position(|host_keys| {
+                host_keys
+                    .iter()
+                    .any(|bound| bound.eq_ignore_ascii_case(host_key))
+            })
+            .map(|key| key as u8)
+    }
+    pub fn to_toml(&self) -> String {
+        let mut toml = String::from("[keypad]\n");
+        for (key, host_keys) in self.keys.iter().enumerate() {
+            let host_keys: Vec<String> = host_keys
+                .iter()
+                .map(|host_key| format!("{host_key:?}"))
+                .collect();
+            let _ = writeln!(toml, "{key:X} = [{}]", host_keys.join(", "));
+        }
+        toml
+    }
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error + 'static>> {
+        fs::write(path, self.to_toml()).map_err(|e| format!("{e}: {}", path.display()))?;
+        Ok(())
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::preset("qwerty").unwrap()
+    }
+}
+
+// Asks for every key in keypad order in the window, Escape keeps the current binding.
+// Returns None if the window was closed before the last key
+pub fn bind(sdl_context: &Sdl, display: &mut impl DisplayTrait, keymap: &Keymap) -> Option<Keymap> {
+    let mut keymap = keymap.clone();
+    let framebuffer = Framebuffer::new();
+    let mut event_pump = sdl_context.event_pump().unwrap();
+    for key in KEYPAD {
+        let mut highlighted = [false; 16];
+        highlighted[key as usize] = true;
+        display.show_keys(highlighted);
+        let keycode = loop {
+            let pressed = event_pump.poll_iter().find_map(|event| match event {
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                } => Some(Some(keycode)),
+                Event::Quit { .. } => Some(None),
+                _ => None,
+            });
+            match pressed {
+                Some(Some(keycode)) => break keycode,
+                Some(None) => return None,
+                None => {}
+            }
+            display.show_message(format!("Press the key for {key:X}, Esc to keep it").as_str());
+            display.end_frame(&framebuffer);
+            thread::sleep(Duration::from_millis(16));
+        };
+        if keycode != Keycode::Escape {
+            keymap.keys[key as usize] = vec![keycode.name()];
+        }
+    }
+    Some(keymap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn should_map_presets_to_keypad_positions() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.key("1"), Some(0x1));
+        assert_eq!(keymap.key("4"), Some(0xC));
+        assert_eq!(keymap.key("x"), Some(0x0));
+        assert_eq!(keymap.key("P"), None);
+        let azerty = Keymap::preset("azerty").unwrap();
+        assert_eq!(azerty.key("A"), Some(0x4));
+        assert_eq!(azerty.key("W"), Some(0xA));
+    }
+    #[test]
+    fn should_reject_keys_taken_by_hotkeys() {
+        let hotkeys = Hotkeys::new(&BTreeMap::new(), |_| true).unwrap();
+        for (preset, _) in PRESETS {
+            let keymap = Keymap::preset(preset).unwrap();
+            assert!(keymap.check_hotkeys(&hotkeys).is_ok(), "{preset}");
+        }
+        let overrides = BTreeMap::from([("pause".to_string(), "P".to_string())]);
+        let hotkeys = Hotkeys::new(&overrides, |_| true).unwrap();
+        let dvorak = Keymap::preset("dvorak").unwrap();
+        assert!(dvorak.check_hotkeys(&hotkeys).is_err());
+    }
+    #[test]
+    fn should_bind_several_host_keys_to_one_key() {
+        let mut keymap = Keymap::default();
+        let keypad =
+            KeypadConfig::from([("5".to_string(), vec!["Up".to_string(), "K".to_string()])]);
+        keymap.apply(&keypad).unwrap();
+        assert_eq!(keymap.key("Up"), Some(0x5));
+        assert_eq!(keymap.key("K"), Some(0x5));
+        assert_eq!(keymap.key("W"), None);
+        let keypad = KeypadConfig::from([("6".to_string(), vec!["q".to_string()])]);
+        keymap.apply(&keypad).unwrap();
+        assert_eq!(keymap.key("Q"), Some(0x6));
+        let keypad = KeypadConfig::from([("G".to_string(), vec!["Up".to_string()])]);
+        assert!(keymap.apply(&keypad).is_err());
+    }
+    #[test]
+    fn should_read_back_saved_keymaps() {
+        let mut keymap = Keymap::preset("dvorak").unwrap();
+        let keypad =
+            KeypadConfig::from([("0".to_string(), vec!["Space".to_string(), "Q".to_string()])]);
+        keymap.apply(&keypad).unwrap();
+        let path = std::env::temp_dir().join("chip-8-emulator-keymap-test.toml");
+        keymap.save(&path).unwrap();
+        assert_eq!(Keymap::from_arg(path.to_str().unwrap()).unwrap(), keymap);
+        let _ = fs::remove_file(path);
+    }
+}
@@ -1,43 +1,614 @@
+mod audio;
+mod config;
+#[cfg(test)]
+mod conformance;
+mod controller;
 mod cpu;
+mod database;
 mod debugger;
 mod display;
+mod font;
+mod framebuffer;
+mod hotkeys;
+mod info;
+mod input;
 mod keyboard;
+mod keymap;
 mod memory;
-use clap::Parser;
-use cpu::CPU;
-use display::Display;
+mod opcode;
+mod osd;
+mod palette;
+mod platform;
+mod protection;
+mod quirks;
+mod recorder;
+mod screenshot;
+mod terminal;
+mod touchpad;
+use audio::{AudioSink, CombinedAudio, RodioAudio, Tone, WavAudio, Waveform};
+use clap::{Parser, Subcommand, ValueEnum};
+use config::{AudioConfig, Config};
+use controller::{ControllerMapping, Controllers};
+use cpu::{CPU, INSTRUCTIONS_PER_FRAME};
+use database::{Database, Entry};
+use display::{Display, DisplayOptions, DisplayTrait, HeadlessDisplay, PresentMode, Scaling};
+use font::{Font, FontSet};
+use hotkeys::Hotkeys;
+use info::Info;
+use input::{CombinedInput, InputSource, MovieInput, NetworkInput, ScriptInput, SdlInput};
 use keyboard::Keyboard;
-use memory::Memory;
+use keymap::Keymap;
+use memory::{Layout, Memory};
+use palette::Palette;
+use platform::Platform;
+use protection::Protection;
+use quirks::{Quirks, QuirksProfile};
+use recorder::Recorder;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use terminal::{Glyphs, TerminalDisplay, TerminalInput};
+use touchpad::{TouchKeypad, Touchpad};
 
 const DEFAULT_ROM: &str = "roms/spaceinvaders.ch8";
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Frontend {
+    Sdl,
+    Terminal,
+    // No window and no frame pacing, runs --frames frames as fast as possible
+    Headless,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print what is known about a ROM without running it
+    Info {
+        /// ROM file, or - to read it from stdin
+        rom: String,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(short, long)]
     debug: bool,
+    /// ROM file to run, or - to read it from stdin
     #[arg(short, long, default_value_t = DEFAULT_ROM.to_string())]
     rom: String,
+    /// Where the ROM loads and starts, 0x200 for the VIP, 0x600 for the ETI-660 or 0 for raw images,
+    /// and 64K of memory for XO-CHIP. The ROM database's platform, or the VIP, by default
+    #[arg(long, value_enum, global = true)]
+    platform: Option<Platform>,
+    /// Hex address to load the ROM at instead of the platform's
+    #[arg(long, value_parser = memory::parse_address, global = true)]
+    load_addr: Option<u16>,
+    /// Hex address to start running at, the load address by default
+    #[arg(long, value_parser = memory::parse_address, global = true)]
+    entry: Option<u16>,
+    /// Allow the ROM to load over the font
+    #[arg(long, global = true)]
+    overwrite_font: bool,
+    /// Small font for Fx29, followed by the SUPER-CHIP big font for Fx30
+    #[arg(long, value_enum, default_value_t = FontSet::default())]
+    font: FontSet,
+    /// Load the font from a file of 80 bytes, or 180 or 240 with a big font
+    #[arg(long, conflicts_with = "font")]
+    font_file: Option<PathBuf>,
+    /// Hex address to load the font at, many interpreters use 0x50
+    #[arg(long, value_parser = memory::parse_address, default_value = "0")]
+    font_addr: u16,
+    /// Quirks profile, from the ROM database when it knows the ROM, cowgod otherwise
+    #[arg(short, long, value_enum)]
+    quirks: Option<QuirksProfile>,
+    /// Instructions per frame, 8 unless the ROM database says otherwise
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    tick_rate: Option<u32>,
+    /// Also look ROMs up in a JSON file in the format of the community CHIP-8 database
+    #[arg(long, value_name = "FILE", global = true)]
+    database: Vec<PathBuf>,
+    /// Don't look the ROM up in any database
+    #[arg(long, conflicts_with = "database", global = true)]
+    no_database: bool,
+    /// Watch memory accesses, warning about every violation or as told, like error,code-write=break
+    #[arg(long, num_args = 0..=1, default_missing_value = "", value_name = "ACTIONS")]
+    strict: Option<Protection>,
+    #[arg(short, long, value_enum, default_value_t = Frontend::Sdl)]
+    frontend: Frontend,
+    /// Number of frames to run with the headless frontend
+    #[arg(long, required_if_eq("frontend", "headless"))]
+    frames: Option<u32>,
+    /// Characters used to draw the screen with the terminal frontend
+    #[arg(long, value_enum, default_value_t = Glyphs::default())]
+    glyphs: Glyphs,
+    /// Milliseconds after which a key is released with the terminal frontend
+    #[arg(long, default_value_t = 250)]
+    key_timeout: u64,
+    /// Preset name (classic, green-phosphor, amber, lcd, octo) or #RRGGBB,#RRGGBB
+    #[arg(short, long)]
+    palette: Option<Palette>,
+    /// When the screen is presented, present once per frame to reduce flicker
+    #[arg(long, value_enum, default_value_t = PresentMode::default())]
+    present: PresentMode,
+    /// Let pixels fade out over a few frames like a phosphor screen
+    #[arg(long)]
+    ghosting: bool,
+    /// Initial window size as a multiple of 64x32
+    #[arg(short, long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    scale: u32,
+    /// How the screen is scaled when the window is resized
+    #[arg(long, value_enum, default_value_t = Scaling::default())]
+    scaling: Scaling,
+    /// Show frames and instructions per second on screen
+    #[arg(long)]
+    stats: bool,
+    /// Show which keys are held on screen
+    #[arg(long)]
+    keypad_overlay: bool,
+    /// Show a keypad that can be clicked or touched, beside or under the screen
+    #[arg(long, value_enum, value_name = "WHERE")]
+    touch_keypad: Option<TouchKeypad>,
+    /// Keypad layout, qwerty, azerty, dvorak or a keymap file. Replaces the keypad bindings of the config file
+    #[arg(short, long)]
+    keymap: Option<String>,
+    /// Ask for every key in the window and save the bindings to a keymap file
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["debug", "frontend", "input_script", "movie", "listen"]
+    )]
+    bind_keys: Option<String>,
+    /// Press keys from a script of wait <frames>, press <keys>, release <keys> and quit lines
+    #[arg(long, value_name = "FILE")]
+    input_script: Option<String>,
+    /// Replay the keys held on every frame from <frame> <keys> lines
+    #[arg(long, value_name = "FILE")]
+    movie: Option<String>,
+    /// Take press, release and quit lines over TCP, e.g. 127.0.0.1:8000
+    #[arg(long, value_name = "ADDRESS")]
+    listen: Option<String>,
+    /// Don't open an audio device, the buzzer stays silent
+    #[arg(long)]
+    no_audio: bool,
+    /// Pitch of the buzzer in Hz, 440 by default
+    #[arg(long, value_name = "HZ")]
+    buzzer_frequency: Option<f32>,
+    /// Shape of the buzzer tone, square by default like the COSMAC VIP
+    #[arg(long, value_enum)]
+    buzzer_waveform: Option<Waveform>,
+    /// Buzzer volume from 0 to 1, 0.25 by default
+    #[arg(long)]
+    volume: Option<f32>,
+    /// Also write the buzzer to a WAV file, following emulated time
+    #[arg(long, value_name = "FILE")]
+    audio_out: Option<String>,
+    /// Config file, defaults to ~/.config/chip8/config.toml
+    #[arg(short, long, global = true)]
+    config: Option<String>,
+    /// Size of screenshots as a multiple of 64x32
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    screenshot_scale: u32,
+    /// Run without a window for N frames, save a screenshot and exit
+    #[arg(long, value_name = "N")]
+    screenshot_at_frame: Option<u32>,
+    /// Where --screenshot-at-frame saves the image, defaults to <rom>.png
+    #[arg(long, requires = "screenshot_at_frame")]
+    screenshot_file: Option<String>,
+    /// Record from the first frame to a .gif, .png (APNG) or .y4m file, or - for y4m on stdout
+    #[arg(long, value_name = "FILE")]
+    record: Option<String>,
+    /// Where the buzzer goes when recording y4m, defaults to the video file with a .wav extension
+    #[arg(long, value_name = "FILE", requires = "record")]
+    record_wav: Option<String>,
+    /// Size of recordings as a multiple of 64x32
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    record_scale: u32,
+}
+
+// --keymap on its own, or the config keypad with the overrides for this ROM on top
+fn load_keymap(
+    args: &Args,
+    config: &Config,
+    memory: &Memory,
+    hotkeys: &Hotkeys,
+) -> Result<Keymap, Box<dyn std::error::Error + 'static>> {
+    let keymap = match args.keymap.as_deref() {
+        Some(keymap) => Keymap::from_arg(keymap)?,
+        None => {
+            let mut keymap = Keymap::default();
+            keymap.apply(&config.keypad)?;
+            if let Some(rom) = config.rom(memory.rom_sha1().as_str()) {
+                keymap.apply(&rom.keypad)?;
+            }
+            keymap
+        }
+    };
+    keymap.check(keyboard::is_key_name)?;
+    keymap.check_hotkeys(hotkeys)?;
+    Ok(keymap)
+}
+
+// The config's mapping, then the database's key hints for this ROM, then the config's for this ROM
+fn controller_mapping(
+    config: &Config,
+    entry: Option<Entry<'_>>,
+    memory: &Memory,
+) -> Result<ControllerMapping, String> {
+    let hints = entry.map(|entry| entry.controller()).unwrap_or_default();
+    match config.rom(memory.rom_sha1().as_str()) {
+        Some(rom) => ControllerMapping::new(&[&config.controller, &hints, &rom.controller]),
+        None => ControllerMapping::new(&[&config.controller, &hints]),
+    }
+}
+
+// The embedded database with the config's files and then --database on top
+fn load_database(args: &Args, config: &Config) -> Result<Database, String> {
+    let mut database = Database::embedded();
+    for path in config.databases.iter().chain(&args.database) {
+        database.extend(path)?;
+    }
+    Ok(database)
+}
+
+// Sources that play along with the frontend's own input
+fn extra_inputs(args: &Args) -> Result<Vec<Box<dyn InputSource>>, Box<dyn std::error::Error>> {
+    let mut inputs: Vec<Box<dyn InputSource>> = Vec::new();
+    if let Some(path) = args.input_script.as_deref() {
+        inputs.push(Box::new(ScriptInput::load(path)?));
+    }
+    if let Some(path) = args.movie.as_deref() {
+        inputs.push(Box::new(MovieInput::load(path)?));
+    }
+    if let Some(address) = args.listen.as_deref() {
+        let network = NetworkInput::listen(address)?;
+        eprintln!("Listening for input on {}", network.address());
+        inputs.push(Box::new(network));
+    }
+    Ok(inputs)
+}
+
+// The platform's layout with the addresses given on the command line
+fn layout(args: &Args, entry: Option<Entry<'_>>) -> Layout {
+    let preset = args
+        .platform
+        .or(entry.and_then(|entry| entry.platform()))
+        .unwrap_or_default()
+        .layout();
+    let load_address = args.load_addr.unwrap_or(preset.load_address);
+    Layout {
+        load_address,
+        entry: args
+            .entry
+            .unwrap_or(args.load_addr.map_or(preset.entry, |_| load_address)),
+        overwrite_font: preset.overwrite_font || args.overwrite_font,
+        memory_size: preset.memory_size,
+    }
+}
+
+fn font(args: &Args) -> Result<Font, String> {
+    let mut font = match &args.font_file {
+        Some(path) => Font::from_file(path)?,
+        None => Font::new(args.font),
+    };
+    font.set_base(args.font_addr)?;
+    Ok(font)
+}
+
+// The audio device unless --no-audio or headless, running silently without one rather than
+// failing, and the --audio-out file
+fn audio_sink(args: &Args, tone: Tone, headless: bool) -> Box<dyn AudioSink> {
+    let mut sinks: Vec<Box<dyn AudioSink>> = Vec::new();
+    if !args.no_audio && !headless {
+        match RodioAudio::new(tone) {
+            Ok(audio) => sinks.push(Box::new(audio)),
+            Err(e) => eprintln!("Couldn't open an audio device, running without sound: {e}"),
+        }
+    }
+    if let Some(path) = args.audio_out.as_deref() {
+        match WavAudio::create(Path::new(path), tone) {
+            Ok(audio) => sinks.push(Box::new(audio)),
+            Err(e) => {
+                eprintln!("Couldn't write audio: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+    Box::new(CombinedAudio::new(sinks))
+}
+
+// Once the CPU stops, so the files the sink writes are complete
+fn finish_audio(audio: &mut dyn AudioSink) {
+    if let Err(e) = audio.finish() {
+        eprintln!("Couldn't finish audio: {e}");
+    }
+}
+
+// The ROM is looked up before it is loaded, the database's platform says where it goes
+fn load_rom<'a>(
+    args: &Args,
+    database: &'a Database,
+    file_path: &str,
+    mut memory: Memory,
+) -> (Memory, Option<Entry<'a>>) {
+    let rom = memory::read_rom(file_path).unwrap_or_else(|e| {
+        eprintln!("Couldn't load the ROM: {e}");
+        std::process::exit(1);
+    });
+    let entry = match args.no_database {
+        true => None,
+        false => database.find(memory::sha1(&rom).as_str()),
+    };
+    memory.set_layout(layout(args, entry));
+    if let Err(e) = memory.load_from(file_path, &rom) {
+        eprintln!("Couldn't load the ROM: {e}");
+        std::process::exit(1);
+    }
+    if let Some(warning) = memory.warning() {
+        eprintln!("Warning: {warning}");
+    }
+    (memory, entry)
+}
+
+// The info subcommand, which loads the ROM the same way as running it but never runs it
+fn print_info(args: &Args, config: &Config, rom: &str, json: bool) {
+    let database = load_database(args, config).unwrap_or_else(|e| {
+        eprintln!("Couldn't load ROM database: {e}");
+        std::process::exit(1);
+    });
+    let (memory, entry) = load_rom(args, &database, rom, Memory::new());
+    let info = Info::new(rom, &memory, entry);
+    match json {
+        true => println!("{}", serde_json::to_string_pretty(&info).unwrap()),
+        false => println!("{info}"),
+    }
+}
+
+// Settings shared by every frontend
+fn new_cpu<D: DisplayTrait>(
+    memory: Memory,
+    display: D,
+    quirks: Quirks,
+    tick_rate: usize,
+    args: &Args,
+    recorder: Option<Recorder>,
+) -> CPU<D> {
+    let mut cpu = CPU::new(memory, display, Keyboard::new(), quirks);
+    cpu.set_tick_rate(tick_rate);
+    if let Some(protection) = args.strict {
+        cpu.set_protection(protection);
+    }
+    cpu.set_screenshot_scale(args.screenshot_scale as usize);
+    cpu.set_record_scale(args.record_scale as usize);
+    if let Some(recorder) = recorder {
+        cpu.start_recording(recorder);
+    }
+    cpu
 }
 
 fn main() {
     let args = Args::parse();
+    // The terminal frontend and the debugger draw on stdout, where the video would go
+    if args.record.as_deref() == Some("-") && (args.frontend == Frontend::Terminal || args.debug) {
+        eprintln!("--record=- writes to stdout, which --frontend=terminal and --debug use");
+        std::process::exit(1);
+    }
+    let config = match Config::load(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Couldn't load config: {e}");
+            std::process::exit(1);
+        }
+    };
+    if let Some(Command::Info { rom, json }) = &args.command {
+        print_info(&args, &config, rom, *json);
+        return;
+    }
+    let hotkeys = Hotkeys::new(&config.hotkeys, keyboard::is_key_name).unwrap_or_else(|e| {
+        eprintln!("Invalid hotkeys in config: {e}");
+        std::process::exit(1);
+    });
+    let font = font(&args).unwrap_or_else(|e| {
+        eprintln!("Invalid font: {e}");
+        std::process::exit(1);
+    });
+    let database = load_database(&args, &config).unwrap_or_else(|e| {
+        eprintln!("Couldn't load ROM database: {e}");
+        std::process::exit(1);
+    });
     let mut memory = Memory::new();
-    match memory.load(args.rom.as_str()) {
-        Ok(_) => {
-            //memory.display();
+    memory.set_font(font);
+    let (memory, entry) = load_rom(&args, &database, args.rom.as_str(), memory);
+    // The command line wins over the database, which wins over the config file
+    let palette = match args.palette.clone() {
+        Some(palette) => palette,
+        None => match entry.map(|entry| entry.palette()).transpose() {
+            Ok(Some(Some(palette))) => palette,
+            Ok(_) => config.palette.palette().unwrap_or_else(|e| {
+                eprintln!("Invalid palette in config: {e}");
+                std::process::exit(1);
+            }),
+            Err(e) => {
+                eprintln!("Invalid colours in ROM database: {e}");
+                std::process::exit(1);
+            }
+        },
+    };
+    let keymap = load_keymap(&args, &config, &memory, &hotkeys).unwrap_or_else(|e| {
+        eprintln!("Invalid keymap: {e}");
+        std::process::exit(1);
+    });
+    let controller_mapping = controller_mapping(&config, entry, &memory).unwrap_or_else(|e| {
+        eprintln!("Invalid controller mapping: {e}");
+        std::process::exit(1);
+    });
+    let rom_name = match args.rom.as_str() {
+        "-" => "stdin".to_string(),
+        rom => Path::new(rom)
+            .file_stem()
+            .map_or(rom.to_string(), |stem| stem.to_string_lossy().to_string()),
+    };
+    let title = entry.map_or(rom_name.clone(), |entry| entry.program.title.clone());
+    let display_options = DisplayOptions {
+        title: format!("CHIP-8 Emulator by luizf - {title}"),
+        palette,
+        present_mode: args.present,
+        ghosting: args.ghosting,
+        scale: args.scale,
+        scaling: args.scaling,
+        show_stats: args.stats,
+        show_keypad: args.keypad_overlay,
+        touch_keypad: args.touch_keypad,
+    };
+    let quirks = match (args.quirks, entry.and_then(|entry| entry.quirks())) {
+        (Some(profile), _) => Quirks::new(profile),
+        (None, Some((_, quirks))) => quirks,
+        (None, None) => Quirks::default(),
+    };
+    let tick_rate = args
+        .tick_rate
+        .or(entry.and_then(|entry| entry.rom.tickrate))
+        .map_or(INSTRUCTIONS_PER_FRAME, |tick_rate| tick_rate as usize);
+    let tone = AudioConfig {
+        frequency: args.buzzer_frequency.or(config.audio.frequency),
+        waveform: args.buzzer_waveform.or(config.audio.waveform),
+        volume: args.volume.or(config.audio.volume),
+    }
+    .tone()
+    .unwrap_or_else(|e| {
+        eprintln!("Invalid buzzer settings: {e}");
+        std::process::exit(1);
+    });
+    let mut inputs = extra_inputs(&args).unwrap_or_else(|e| {
+        eprintln!("Couldn't set up input: {e}");
+        std::process::exit(1);
+    });
+    if let Some(frames) = args.screenshot_at_frame {
+        let display = HeadlessDisplay::new(display_options.palette.clone());
+        let mut audio = audio_sink(&args, tone, true);
+        let mut cpu = new_cpu(memory, display, quirks, tick_rate, &args, None);
+        cpu.run_frames(frames, &mut CombinedInput::new(inputs), &mut audio);
+        finish_audio(audio.as_mut());
+        if cpu.failed() {
+            std::process::exit(1);
         }
-        Err(e) => {
-            panic!("{e}: {}", args.rom.as_str());
+        let path = args
+            .screenshot_file
+            .clone()
+            .unwrap_or(format!("{rom_name}.png"));
+        match cpu.screenshot(
+            Some(Path::new(path.as_str())),
+            args.screenshot_scale as usize,
+        ) {
+            Ok(path) => eprintln!("Saved screenshot to {}", path.display()),
+            Err(e) => {
+                eprintln!("Couldn't save screenshot: {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if let Some(path) = args.bind_keys.as_deref() {
+        let sdl_context = sdl2::init().unwrap();
+        let options = DisplayOptions {
+            show_keypad: true,
+            ..display_options
+        };
+        let canvas = Display::canvas(&sdl_context, &options);
+        let texture_creator = canvas.texture_creator();
+        let mut display = Display::new(canvas, &texture_creator, options);
+        if let Some(keymap) = keymap::bind(&sdl_context, &mut display, &keymap) {
+            match keymap.save(Path::new(path)) {
+                Ok(()) => println!("Saved keymap to {path}, use it with --keymap {path}"),
+                Err(e) => {
+                    eprintln!("Couldn't save keymap: {e}");
+                    std::process::exit(1);
+                }
+            }
         }
+        return;
     }
-    let sdl_context = sdl2::init().unwrap();
-    let display = Display::new(&sdl_context);
-    let keyboard = Keyboard::new();
-    let mut cpu = CPU::new(memory, display, keyboard);
-    if args.debug {
-        cpu.run_debug(&sdl_context);
-    } else {
-        cpu.run(&sdl_context);
+    let recorder = args.record.as_deref().map(|path| {
+        let audio = args.record_wav.as_deref().map(Path::new);
+        Recorder::create(
+            Path::new(path),
+            audio,
+            tone,
+            display_options.palette.clone(),
+            args.record_scale as usize,
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("Couldn't start recording: {e}");
+            std::process::exit(1);
+        })
+    });
+    // Exits once the frontend is gone, so the terminal is back to normal
+    let failed = match args.frontend {
+        Frontend::Sdl => {
+            let sdl_context = sdl2::init().unwrap();
+            let canvas = Display::canvas(&sdl_context, &display_options);
+            let texture_creator = canvas.texture_creator();
+            let display = Display::new(canvas, &texture_creator, display_options);
+            let controllers = Controllers::new(&sdl_context, controller_mapping)
+                .expect("Couldn't set up game controllers");
+            let window_size = touchpad::window_size(args.scale, args.touch_keypad);
+            let touchpad = Touchpad::new(args.touch_keypad, window_size);
+            let sdl_input = SdlInput::new(&sdl_context, hotkeys, keymap, controllers, touchpad)
+                .expect("Couldn't set up SDL input");
+            inputs.insert(0, Box::new(sdl_input));
+            let mut input = CombinedInput::new(inputs);
+            let mut audio = audio_sink(&args, tone, false);
+            let mut cpu = new_cpu(memory, display, quirks, tick_rate, &args, recorder);
+            if args.debug {
+                cpu.run_debug(&mut input, &mut audio);
+            } else {
+                cpu.run(&mut input, &mut audio);
+            }
+            finish_audio(audio.as_mut());
+            cpu.failed()
+        }
+        Frontend::Terminal => {
+            let mut display = TerminalDisplay::new(args.glyphs, display_options)
+                .expect("Couldn't set up the terminal");
+            if args.debug {
+                display
+                    .set_debugger()
+                    .expect("Couldn't make room for the debugger");
+            }
+            let terminal_input =
+                TerminalInput::new(Duration::from_millis(args.key_timeout), hotkeys, keymap)
+                    .expect("Couldn't enable raw mode");
+            inputs.insert(0, Box::new(terminal_input));
+            let mut input = CombinedInput::new(inputs);
+            let mut audio = audio_sink(&args, tone, false);
+            let mut cpu = new_cpu(memory, display, quirks, tick_rate, &args, recorder);
+            if args.debug {
+                cpu.run_debug(&mut input, &mut audio);
+            } else {
+                cpu.run(&mut input, &mut audio);
+            }
+            finish_audio(audio.as_mut());
+            cpu.failed()
+        }
+        Frontend::Headless => {
+            let display = HeadlessDisplay::new(display_options.palette);
+            let mut audio = audio_sink(&args, tone, true);
+            let mut cpu = new_cpu(memory, display, quirks, tick_rate, &args, recorder);
+            cpu.run_frames(
+                args.frames.unwrap_or_default(),
+                &mut CombinedInput::new(inputs),
+                &mut audio,
+            );
+            cpu.stop_recording();
+            finish_audio(audio.as_mut());
+            cpu.failed()
+        }
+    };
+    if failed {
+        std::process::exit(1);
     }
 }
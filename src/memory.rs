@@ -1,51 +1,308 @@
+use crate::font::Font;
+use crate::protection::Violation;
+use std::fmt;
 use std::fs;
+use std::io::{self, ErrorKind, Read};
+use std::mem;
+use std::ops::Range;
+// The 4K of the COSMAC VIP, the platform can raise it up to the 64K of XO-CHIP
 const MEMORY_SIZE: usize = 0x1000;
+pub const MAX_MEMORY_SIZE: usize = 0x10000;
 const STACK_SIZE: usize = 0x10;
-const ROM_START: usize = 0x200;
+const ROM_START: u16 = 0x200;
+
+// Where the ROM goes in memory and where it starts running
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Layout {
+    pub load_address: u16,
+    pub entry: u16,
+    // Lets the ROM load over the font, for memory images that bring their own
+    pub overwrite_font: bool,
+    pub memory_size: usize,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            load_address: ROM_START,
+            entry: ROM_START,
+            overwrite_font: false,
+            memory_size: MEMORY_SIZE,
+        }
+    }
+}
+
+// A hex address inside memory, with or without 0x
+pub fn parse_address(address: &str) -> Result<u16, String> {
+    let hex = address.trim_start_matches("0x").trim_start_matches("0X");
+    match u32::from_str_radix(hex, 16) {
+        Ok(address) if (address as usize) < MAX_MEMORY_SIZE => Ok(address as u16),
+        Ok(_) => Err(format!(
+            "{address} is past the end of memory at 0x{MAX_MEMORY_SIZE:X}"
+        )),
+        Err(_) => Err(format!("{address} isn't a hex address")),
+    }
+}
+
+// A file, or - for stdin
+pub fn read_rom(file_path: &str) -> Result<Vec<u8>, RomError> {
+    if file_path == "-" {
+        let mut rom = Vec::new();
+        io::stdin()
+            .read_to_end(&mut rom)
+            .map_err(|e| RomError::Unreadable("stdin".to_string(), e))?;
+        return Ok(rom);
+    }
+    fs::read(file_path).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => RomError::NotFound(file_path.to_string()),
+        _ => RomError::Unreadable(file_path.to_string(), e),
+    })
+}
+
+// Lowercase hex, the way ROMs are identified in the config file and the database
+pub fn sha1(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    NotFound(String),
+    Unreadable(String, io::Error),
+    Empty,
+    TooLarge {
+        size: usize,
+        max: usize,
+        memory_size: usize,
+    },
+    // The load address or the font past the end of the memory of the platform
+    PastEnd {
+        address: usize,
+        memory_size: usize,
+    },
+    OverwritesFont {
+        load_address: u16,
+        font: Range<usize>,
+    },
+    // Only ever a warning, instructions are 2 bytes but a ROM can end on a data byte
+    OddLength(usize),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(path) => write!(f, "There is no ROM at {path}"),
+            Self::Unreadable(path, e) => write!(f, "Couldn't read {path}: {e}"),
+            Self::Empty => write!(f, "The ROM is empty"),
+            Self::TooLarge {
+                size,
+                max,
+                memory_size,
+            } => write!(
+                f,
+                "The ROM is {size} bytes, but only {max} fit in {}K of memory",
+                memory_size / 1024
+            ),
+            Self::PastEnd {
+                address,
+                memory_size,
+            } => write!(
+                f,
+                "0x{address:03X} is past the end of {}K of memory",
+                memory_size / 1024
+            ),
+            Self::OverwritesFont { load_address, font } => write!(
+                f,
+                "Loading the ROM at 0x{load_address:03X} would overwrite the font at 0x{:03X} to 0x{:03X}",
+                font.start,
+                font.end - 1
+            ),
+            Self::OddLength(size) => write!(
+                f,
+                "The ROM is {size} bytes, an odd length, so it doesn't end on a whole instruction"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+// Where the ROM came from, to load it again on reset
+#[derive(Clone, Debug)]
+enum RomSource {
+    File(String),
+    Bytes(Vec<u8>),
+}
+
 pub struct Memory {
-    pub memory: [u8; MEMORY_SIZE],
+    pub memory: Vec<u8>,
     rom_size: usize,
+    rom_source: Option<RomSource>,
+    layout: Layout,
+    font: Font,
+    // What strict mode needs to know about every byte, kept up to date either way
+    written: Vec<bool>,
+    executed: Vec<bool>,
+    violations: Vec<Violation>,
     pub stack: [u16; STACK_SIZE],
 }
 impl Memory {
     pub fn new() -> Self {
         Self {
-            memory: [0; MEMORY_SIZE],
+            memory: vec![0; MEMORY_SIZE],
             rom_size: 0,
+            rom_source: None,
+            layout: Layout::default(),
+            font: Font::default(),
+            written: vec![false; MEMORY_SIZE],
+            executed: vec![false; MEMORY_SIZE],
+            violations: Vec::new(),
             stack: [0; STACK_SIZE],
         }
     }
-    pub fn load(&mut self, file_path: &str) -> Result<(), Box<dyn std::error::Error + 'static>> {
+    // Before loading a ROM, which then goes where the layout says
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+        self.memory = vec![0; layout.memory_size];
+        self.written = vec![false; layout.memory_size];
+        self.executed = vec![false; layout.memory_size];
+    }
+    // Before loading a ROM too, the font is loaded with it
+    pub fn set_font(&mut self, font: Font) {
+        self.font = font;
+    }
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+    pub fn load_address(&self) -> u16 {
+        self.layout.load_address
+    }
+    pub fn entry(&self) -> u16 {
+        self.layout.entry
+    }
+    // A file, or - to read the ROM from stdin
+    pub fn load(&mut self, file_path: &str) -> Result<(), RomError> {
+        let rom = read_rom(file_path)?;
+        self.load_from(file_path, &rom)
+    }
+    // A ROM already read from `file_path`, to look it up before knowing where it goes
+    pub fn load_from(&mut self, file_path: &str, rom: &[u8]) -> Result<(), RomError> {
+        self.load_bytes(rom)?;
+        if file_path != "-" {
+            self.rom_source = Some(RomSource::File(file_path.to_string()));
+        }
+        Ok(())
+    }
+    pub fn load_bytes(&mut self, rom: &[u8]) -> Result<(), RomError> {
+        if rom.is_empty() {
+            return Err(RomError::Empty);
+        }
+        let start = self.layout.load_address as usize;
+        let font = self.font.range();
+        let memory_size = self.layout.memory_size;
+        if let Some(address) = [start, font.end - 1]
+            .into_iter()
+            .find(|address| *address >= memory_size)
+        {
+            return Err(RomError::PastEnd {
+                address,
+                memory_size,
+            });
+        }
+        if start < font.end && start + rom.len() > font.start && !self.layout.overwrite_font {
+            return Err(RomError::OverwritesFont {
+                load_address: self.layout.load_address,
+                font,
+            });
+        }
+        if rom.len() > memory_size - start {
+            return Err(RomError::TooLarge {
+                size: rom.len(),
+                max: memory_size - start,
+                memory_size,
+            });
+        }
         self.load_sprites();
-        let temp = fs::read(file_path)?;
-        self.rom_size = temp.len();
-        temp.iter().enumerate().for_each(|(i, b)| {
-            self.memory[ROM_START + i] = *b;
-        });
+        self.memory[start..start + rom.len()].copy_from_slice(rom);
+        self.written[start..start + rom.len()].fill(true);
+        self.rom_size = rom.len();
+        self.rom_source = Some(RomSource::Bytes(rom.to_vec()));
+        Ok(())
+    }
+    // What is odd about the loaded ROM without stopping it from running
+    pub fn warning(&self) -> Option<RomError> {
+        (self.rom_size % 2 == 1).then_some(RomError::OddLength(self.rom_size))
+    }
+    pub fn rom(&self) -> &[u8] {
+        let start = self.layout.load_address as usize;
+        &self.memory[start..start + self.rom_size]
+    }
+    pub fn rom_sha1(&self) -> String {
+        sha1(self.rom())
+    }
+    // Loads the same ROM again into a fresh memory, for a soft reset
+    pub fn reload(&mut self) -> Result<(), Box<dyn std::error::Error + 'static>> {
+        let rom_source = self.rom_source.clone().ok_or("No ROM was loaded")?;
+        let mut memory = Self::new();
+        memory.set_layout(self.layout);
+        memory.set_font(self.font.clone());
+        match rom_source {
+            RomSource::File(path) => memory.load(path.as_str())?,
+            RomSource::Bytes(rom) => memory.load_bytes(&rom)?,
+        }
+        *self = memory;
         Ok(())
     }
     pub fn load_sprites(&mut self) {
-        let sprites = [
-            0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80,
-            0xF0, 0xF0, 0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0,
-            0x10, 0xF0, 0xF0, 0x80, 0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90,
-            0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0, 0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0,
-            0x90, 0xE0, 0x90, 0xE0, 0xF0, 0x80, 0x80, 0x80, 0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0,
-            0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
-        ];
-        sprites
-            .into_iter()
-            .enumerate()
-            .for_each(|(i, n)| self.memory[i] = n);
+        let font = self.font.range();
+        self.written[font.clone()].fill(true);
+        self.memory[font].copy_from_slice(self.font.glyphs());
+    }
+    // Instructions read and write memory through these, so strict mode sees every access.
+    // Addresses past the end wrap around instead of panicking
+    pub fn read(&mut self, address: usize) -> u8 {
+        let address = self.wrap(address);
+        self.memory[address]
+    }
+    pub fn write(&mut self, address: usize, value: u8) {
+        let address = self.wrap(address);
+        if address < self.layout.load_address as usize {
+            self.violations.push(Violation::LowWrite(address));
+        }
+        if self.executed[address] {
+            self.violations.push(Violation::CodeWrite(address));
+        }
+        self.written[address] = true;
+        self.memory[address] = value;
+    }
+    // The two bytes of the instruction at `pc`
+    pub fn fetch(&mut self, pc: u16) -> (u8, u8) {
+        let (lhs, rhs) = (self.wrap(pc as usize), self.wrap(pc as usize + 1));
+        if !self.written[lhs] || !self.written[rhs] {
+            self.violations
+                .push(Violation::UninitializedExecution(pc as usize));
+        }
+        self.executed[lhs] = true;
+        self.executed[rhs] = true;
+        (self.memory[lhs], self.memory[rhs])
+    }
+    fn wrap(&mut self, address: usize) -> usize {
+        if address >= self.memory.len() {
+            self.violations.push(Violation::OutOfBounds(address));
+        }
+        address % self.memory.len()
+    }
+    // What the accesses since the last call ran into
+    pub fn take_violations(&mut self) -> Vec<Violation> {
+        mem::take(&mut self.violations)
     }
     pub fn display(&self) {
         self.memory
             .chunks(2)
-            .skip(ROM_START / 2)
+            .skip(self.layout.load_address as usize / 2)
             .take(self.rom_size / 2)
             .enumerate()
             .for_each(|(i, n)| {
-                print!("{:02X?}: ", ROM_START + i);
+                print!("{:02X?}: ", self.layout.load_address as usize + i);
                 n.iter().for_each(|e| {
                     print!("{:02X?}", e);
                 });
@@ -57,6 +314,8 @@ impl Memory {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::font::FontSet;
+    use crate::platform::Platform;
     #[test]
     fn sprites_were_loaded_correctly() {
         let mut memory = Memory::new();
@@ -80,4 +339,167 @@ mod tests {
                 }
             });
     }
+    #[test]
+    fn should_reload_rom_into_fresh_memory() {
+        let mut memory = Memory::new();
+        memory.load("roms/tests/logo.ch8").unwrap();
+        let rom = memory.memory.clone();
+        memory.memory[0x200] = 0xFF;
+        memory.memory[0xFFF] = 0xFF;
+        memory.stack[0] = 0x300;
+        memory.reload().unwrap();
+        assert_eq!(memory.memory, rom);
+        assert_eq!(memory.stack[0], 0);
+        assert!(Memory::new().reload().is_err());
+    }
+    #[test]
+    fn should_reject_roms_that_do_not_fit() {
+        let mut memory = Memory::new();
+        assert!(matches!(
+            memory.load("roms/tests/missing.ch8"),
+            Err(RomError::NotFound(_))
+        ));
+        assert!(matches!(memory.load_bytes(&[]), Err(RomError::Empty)));
+        assert!(matches!(
+            memory.load_bytes(&[0; 3585]),
+            Err(RomError::TooLarge {
+                size: 3585,
+                max: 3584,
+                memory_size: 0x1000
+            })
+        ));
+        memory.load_bytes(&[0xAA; 3584]).unwrap();
+        assert_eq!(memory.memory[0xFFF], 0xAA);
+        assert!(memory.warning().is_none());
+        memory.load_bytes(&[0x00, 0xE0, 0x12]).unwrap();
+        assert!(matches!(memory.warning(), Some(RomError::OddLength(3))));
+    }
+    #[test]
+    fn should_load_at_the_layout_address() {
+        let eti_660 = Layout {
+            load_address: 0x600,
+            entry: 0x600,
+            overwrite_font: false,
+            memory_size: 0x1000,
+        };
+        let mut memory = Memory::new();
+        memory.set_layout(eti_660);
+        memory.load_bytes(&[0x12, 0x34]).unwrap();
+        assert_eq!(memory.memory[0x600..0x602], [0x12, 0x34]);
+        assert_eq!(memory.rom(), [0x12, 0x34]);
+        assert!(matches!(
+            memory.load_bytes(&[0; 2561]),
+            Err(RomError::TooLarge { max: 2560, .. })
+        ));
+        let raw = Layout {
+            load_address: 0,
+            entry: 0,
+            overwrite_font: false,
+            memory_size: 0x1000,
+        };
+        memory = Memory::new();
+        memory.set_layout(raw);
+        assert!(matches!(
+            memory.load_bytes(&[0xFF]),
+            Err(RomError::OverwritesFont {
+                load_address: 0,
+                ..
+            })
+        ));
+        memory.set_layout(Layout {
+            overwrite_font: true,
+            ..raw
+        });
+        memory.load_bytes(&[0xFF]).unwrap();
+        assert_eq!(memory.memory[..2], [0xFF, 0x90]);
+    }
+    #[test]
+    fn should_fit_roms_in_the_memory_of_the_platform() {
+        let mut memory = Memory::new();
+        memory.set_layout(Platform::Xochip.layout());
+        assert!(matches!(
+            memory.load_bytes(&[0; 0xFE01]),
+            Err(RomError::TooLarge {
+                max: 0xFE00,
+                memory_size: 0x10000,
+                ..
+            })
+        ));
+        memory.load_bytes(&[0xAA; 0xFE00]).unwrap();
+        assert_eq!(memory.read(0xFFFF), 0xAA);
+        assert!(memory.take_violations().is_empty());
+        memory = Memory::new();
+        memory.set_layout(Layout {
+            load_address: 0x1000,
+            ..Layout::default()
+        });
+        assert!(matches!(
+            memory.load_bytes(&[0x12, 0x00]),
+            Err(RomError::PastEnd {
+                address: 0x1000,
+                memory_size: 0x1000
+            })
+        ));
+        let mut font = Font::new(FontSet::Vip);
+        font.set_base(0xF50).unwrap();
+        memory = Memory::new();
+        memory.set_font(font);
+        assert!(matches!(
+            memory.load_bytes(&[0x12, 0x00]),
+            Err(RomError::PastEnd {
+                address: 0x1003,
+                ..
+            })
+        ));
+    }
+    #[test]
+    fn should_parse_addresses() {
+        assert_eq!(parse_address("0x600"), Ok(0x600));
+        assert_eq!(parse_address("200"), Ok(0x200));
+        assert_eq!(parse_address("1000"), Ok(0x1000));
+        assert!(parse_address("10000").is_err());
+        assert!(parse_address("0xZZ").is_err());
+    }
+    #[test]
+    fn should_report_violations_and_wrap_around() {
+        let mut memory = Memory::new();
+        memory.load_bytes(&[0x12, 0x00, 0xAB]).unwrap();
+        assert_eq!(memory.fetch(0x200), (0x12, 0x00));
+        assert_eq!(memory.read(0x1202), 0xAB);
+        memory.write(0x100, 1);
+        memory.write(0x201, 1);
+        memory.write(0x300, 0x60);
+        memory.fetch(0x300);
+        memory.fetch(0x302);
+        assert_eq!(
+            memory.take_violations(),
+            [
+                Violation::OutOfBounds(0x1202),
+                Violation::LowWrite(0x100),
+                Violation::CodeWrite(0x201),
+                Violation::UninitializedExecution(0x300),
+                Violation::UninitializedExecution(0x302),
+            ]
+        );
+        assert!(memory.take_violations().is_empty());
+    }
+    #[test]
+    fn should_reload_rom_from_bytes() {
+        let mut memory = Memory::new();
+        memory.load_bytes(&[0x12, 0x00]).unwrap();
+        memory.memory[0x200] = 0xFF;
+        memory.reload().unwrap();
+        assert_eq!(memory.rom(), [0x12, 0x00]);
+    }
+    #[test]
+    fn should_hash_the_rom_only() {
+        let mut memory = Memory::new();
+        memory.memory[0x200..0x203].copy_from_slice(b"abc");
+        memory.rom_size = 3;
+        assert_eq!(memory.rom(), b"abc");
+        assert_eq!(
+            memory.rom_sha1(),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+    }
 }
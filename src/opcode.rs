@@ -0,0 +1,411 @@
+use serde::Serialize;
+use std::fmt;
+
+// The smallest platform that runs an opcode, in order
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Extension {
+    #[default]
+    Chip8,
+    Superchip,
+    Xochip,
+}
+
+impl fmt::Display for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Chip8 => write!(f, "CHIP-8"),
+            Self::Superchip => write!(f, "SUPER-CHIP"),
+            Self::Xochip => write!(f, "XO-CHIP"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnknownOpcode(pub u16);
+
+impl fmt::Display for UnknownOpcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown opcode: {:04X}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownOpcode {}
+
+// One instruction with its operands, named after Cowgod's mnemonics. Decoding never
+// looks at the machine, so the CPU, the info subcommand and the tests all share it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Opcode {
+    // 0nnn, machine code routines of the VIP
+    Sys { nnn: u16 },
+    // 00E0
+    Cls,
+    // 00EE
+    Ret,
+    // 00Cn, SUPER-CHIP
+    ScrollDown { n: u8 },
+    // 00Dn, XO-CHIP
+    ScrollUp { n: u8 },
+    // 00FB, SUPER-CHIP
+    ScrollRight,
+    // 00FC, SUPER-CHIP
+    ScrollLeft,
+    // 00FD, SUPER-CHIP
+    Exit,
+    // 00FE, SUPER-CHIP
+    Low,
+    // 00FF, SUPER-CHIP
+    High,
+    // 1nnn
+    Jp { nnn: u16 },
+    // 2nnn
+    Call { nnn: u16 },
+    // 3xkk
+    SeVxByte { x: u8, kk: u8 },
+    // 4xkk
+    SneVxByte { x: u8, kk: u8 },
+    // 5xy0
+    SeVxVy { x: u8, y: u8 },
+    // 5xy2, XO-CHIP
+    SaveVxVy { x: u8, y: u8 },
+    // 5xy3, XO-CHIP
+    LoadVxVy { x: u8, y: u8 },
+    // 6xkk
+    LdVxByte { x: u8, kk: u8 },
+    // 7xkk
+    AddVxByte { x: u8, kk: u8 },
+    // 8xy0
+    LdVxVy { x: u8, y: u8 },
+    // 8xy1
+    Or { x: u8, y: u8 },
+    // 8xy2
+    And { x: u8, y: u8 },
+    // 8xy3
+    Xor { x: u8, y: u8 },
+    // 8xy4
+    AddVxVy { x: u8, y: u8 },
+    // 8xy5
+    Sub { x: u8, y: u8 },
+    // 8xy6
+    Shr { x: u8, y: u8 },
+    // 8xy7
+    Subn { x: u8, y: u8 },
+    // 8xyE
+    Shl { x: u8, y: u8 },
+    // 9xy0
+    SneVxVy { x: u8, y: u8 },
+    // Annn
+    LdI { nnn: u16 },
+    // Bnnn
+    JpV0 { nnn: u16 },
+    // Cxkk
+    Rnd { x: u8, kk: u8 },
+    // Dxyn, 16x16 sprites when n is 0 on the SUPER-CHIP
+    Drw { x: u8, y: u8, n: u8 },
+    // Ex9E
+    Skp { x: u8 },
+    // ExA1
+    Sknp { x: u8 },
+    // F000 nnnn, XO-CHIP, the address is the next two bytes
+    LdILong,
+    // F002, XO-CHIP
+    Audio,
+    // Fn01, XO-CHIP
+    Plane { n: u8 },
+    // Fx07
+    LdVxDt { x: u8 },
+    // Fx0A
+    LdVxK { x: u8 },
+    // Fx15
+    LdDtVx { x: u8 },
+    // Fx18
+    LdStVx { x: u8 },
+    // Fx1E
+    AddIVx { x: u8 },
+    // Fx29
+    LdFVx { x: u8 },
+    // Fx30, SUPER-CHIP
+    LdHfVx { x: u8 },
+    // Fx33
+    LdBVx { x: u8 },
+    // Fx3A, XO-CHIP
+    Pitch { x: u8 },
+    // Fx55
+    LdIVx { x: u8 },
+    // Fx65
+    LdVxI { x: u8 },
+    // Fx75, SUPER-CHIP for V0 to V7
+    LdRVx { x: u8 },
+    // Fx85, SUPER-CHIP for V0 to V7
+    LdVxR { x: u8 },
+}
+
+impl Opcode {
+    pub fn decode(opcode: u16) -> Result<Self, UnknownOpcode> {
+        let nnn = opcode & 0xFFF;
+        let x = (opcode >> 8 & 0xF) as u8;
+        let y = (opcode >> 4 & 0xF) as u8;
+        let n = (opcode & 0xF) as u8;
+        let kk = (opcode & 0xFF) as u8;
+        let decoded = match opcode >> 12 {
+            0x0 => match nnn {
+                0x0E0 => Self::Cls,
+                0x0EE => Self::Ret,
+                0x0C0..=0x0CF => Self::ScrollDown { n },
+                0x0D0..=0x0DF => Self::ScrollUp { n },
+                0x0FB => Self::ScrollRight,
+                0x0FC => Self::ScrollLeft,
+                0x0FD => Self::Exit,
+                0x0FE => Self::Low,
+                0x0FF => Self::High,
+                _ => Self::Sys { nnn },
+            },
+            0x1 => Self::Jp { nnn },
+            0x2 => Self::Call { nnn },
+            0x3 => Self::SeVxByte { x, kk },
+            0x4 => Self::SneVxByte { x, kk },
+            0x5 => match n {
+                0x0 => Self::SeVxVy { x, y },
+                0x2 => Self::SaveVxVy { x, y },
+                0x3 => Self::LoadVxVy { x, y },
+                _ => return Err(UnknownOpcode(opcode)),
+            },
+            0x6 => Self::LdVxByte { x, kk },
+            0x7 => Self::AddVxByte { x, kk },
+            0x8 => match n {
+                0x0 => Self::LdVxVy { x, y },
+                0x1 => Self::Or { x, y },
+                0x2 => Self::And { x, y },
+                0x3 => Self::Xor { x, y },
+                0x4 => Self::AddVxVy { x, y },
+                0x5 => Self::Sub { x, y },
+                0x6 => Self::Shr { x, y },
+                0x7 => Self::Subn { x, y },
+                0xE => Self::Shl { x, y },
+                _ => return Err(UnknownOpcode(opcode)),
+            },
+            0x9 if n == 0 => Self::SneVxVy { x, y },
+            0xA => Self::LdI { nnn },
+            0xB => Self::JpV0 { nnn },
+            0xC => Self::Rnd { x, kk },
+            0xD => Self::Drw { x, y, n },
+            0xE => match kk {
+                0x9E => Self::Skp { x },
+                0xA1 => Self::Sknp { x },
+                _ => return Err(UnknownOpcode(opcode)),
+            },
+            0xF => match kk {
+                0x00 if x == 0 => Self::LdILong,
+                0x02 if x == 0 => Self::Audio,
+                0x01 => Self::Plane { n: x },
+                0x07 => Self::LdVxDt { x },
+                0x0A => Self::LdVxK { x },
+                0x15 => Self::LdDtVx { x },
+                0x18 => Self::LdStVx { x },
+                0x1E => Self::AddIVx { x },
+                0x29 => Self::LdFVx { x },
+                0x30 => Self::LdHfVx { x },
+                0x33 => Self::LdBVx { x },
+                0x3A => Self::Pitch { x },
+                0x55 => Self::LdIVx { x },
+                0x65 => Self::LdVxI { x },
+                0x75 => Self::LdRVx { x },
+                0x85 => Self::LdVxR { x },
+                _ => return Err(UnknownOpcode(opcode)),
+            },
+            _ => return Err(UnknownOpcode(opcode)),
+        };
+        Ok(decoded)
+    }
+    // Bytes taken in memory, F000 is followed by the address it loads
+    pub fn size(&self) -> usize {
+        match self {
+            Self::LdILong => 4,
+            _ => 2,
+        }
+    }
+    pub fn extension(&self) -> Extension {
+        match self {
+            Self::ScrollDown { .. }
+            | Self::ScrollRight
+            | Self::ScrollLeft
+            | Self::Exit
+            | Self::Low
+            | Self::High
+            | Self::Drw { n: 0, .. }
+            | Self::LdHfVx { .. } => Extension::Superchip,
+            // The SUPER-CHIP only has 8 flag registers, XO-CHIP has 16
+            Self::LdRVx { x } | Self::LdVxR { x } if *x < 8 => Extension::Superchip,
+            Self::ScrollUp { .. }
+            | Self::SaveVxVy { .. }
+            | Self::LoadVxVy { .. }
+            | Self::LdILong
+            | Self::Audio
+            | Self::Plane { .. }
+            | Self::Pitch { .. }
+            | Self::LdRVx { .. }
+            | Self::LdVxR { .. } => Extension::Xochip,
+            _ => Extension::Chip8,
+        }
+    }
+    // The opcode with letters for its operands, like 6xkk
+    pub fn pattern(&self) -> &'static str {
+        match self {
+            Self::Sys { .. } => "0nnn",
+            Self::Cls => "00E0",
+            Self::Ret => "00EE",
+            Self::ScrollDown { .. } => "00Cn",
+            Self::ScrollUp { .. } => "00Dn",
+            Self::ScrollRight => "00FB",
+            Self::ScrollLeft => "00FC",
+            Self::Exit => "00FD",
+            Self::Low => "00FE",
+            Self::High => "00FF",
+            Self::Jp { .. } => "1nnn",
+            Self::Call { .. } => "2nnn",
+            Self::SeVxByte { .. } => "3xkk",
+            Self::SneVxByte { .. } => "4xkk",
+            Self::SeVxVy { .. } => "5xy0",
+            Self::SaveVxVy { .. } => "5xy2",
+            Self::LoadVxVy { .. } => "5xy3",
+            Self::LdVxByte { .. } => "6xkk",
+            Self::AddVxByte { .. } => "7xkk",
+            Self::LdVxVy { .. } => "8xy0",
+            Self::Or { .. } => "8xy1",
+            Self::And { .. } => "8xy2",
+            Self::Xor { .. } => "8xy3",
+            Self::AddVxVy { .. } => "8xy4",
+            Self::Sub { .. } => "8xy5",
+            Self::Shr { .. } => "8xy6",
+            Self::Subn { .. } => "8xy7",
+            Self::Shl { .. } => "8xyE",
+            Self::SneVxVy { .. } => "9xy0",
+            Self::LdI { .. } => "Annn",
+            Self::JpV0 { .. } => "Bnnn",
+            Self::Rnd { .. } => "Cxkk",
+            Self::Drw { n: 0, .. } => "Dxy0",
+            Self::Drw { .. } => "Dxyn",
+            Self::Skp { .. } => "Ex9E",
+            Self::Sknp { .. } => "ExA1",
+            Self::LdILong => "F000",
+            Self::Audio => "F002",
+            Self::Plane { .. } => "Fn01",
+            Self::LdVxDt { .. } => "Fx07",
+            Self::LdVxK { .. } => "Fx0A",
+            Self::LdDtVx { .. } => "Fx15",
+            Self::LdStVx { .. } => "Fx18",
+            Self::AddIVx { .. } => "Fx1E",
+            Self::LdFVx { .. } => "Fx29",
+            Self::LdHfVx { .. } => "Fx30",
+            Self::LdBVx { .. } => "Fx33",
+            Self::Pitch { .. } => "Fx3A",
+            Self::LdIVx { .. } => "Fx55",
+            Self::LdVxI { .. } => "Fx65",
+            Self::LdRVx { .. } => "Fx75",
+            Self::LdVxR { .. } => "Fx85",
+        }
+    }
+}
+
+// Cowgod's mnemonics, like `LD V0, 0x01` or `DRW V0, V1, 5`
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sys { nnn } => write!(f, "SYS 0x{nnn:03X}"),
+            Self::Cls => write!(f, "CLS"),
+            Self::Ret => write!(f, "RET"),
+            Self::ScrollDown { n } => write!(f, "SCD {n}"),
+            Self::ScrollUp { n } => write!(f, "SCU {n}"),
+            Self::ScrollRight => write!(f, "SCR"),
+            Self::ScrollLeft => write!(f, "SCL"),
+            Self::Exit => write!(f, "EXIT"),
+            Self::Low => write!(f, "LOW"),
+            Self::High => write!(f, "HIGH"),
+            Self::Jp { nnn } => write!(f, "JP 0x{nnn:03X}"),
+            Self::Call { nnn } => write!(f, "CALL 0x{nnn:03X}"),
+            Self::SeVxByte { x, kk } => write!(f, "SE V{x:X}, 0x{kk:02X}"),
+            Self::SneVxByte { x, kk } => write!(f, "SNE V{x:X}, 0x{kk:02X}"),
+            Self::SeVxVy { x, y } => write!(f, "SE V{x:X}, V{y:X}"),
+            Self::SaveVxVy { x, y } => write!(f, "SAVE V{x:X} - V{y:X}"),
+            Self::LoadVxVy { x, y } => write!(f, "LOAD V{x:X} - V{y:X}"),
+            Self::LdVxByte { x, kk } => write!(f, "LD V{x:X}, 0x{kk:02X}"),
+            Self::AddVxByte { x, kk } => write!(f, "ADD V{x:X}, 0x{kk:02X}"),
+            Self::LdVxVy { x, y } => write!(f, "LD V{x:X}, V{y:X}"),
+            Self::Or { x, y } => write!(f, "OR V{x:X}, V{y:X}"),
+            Self::And { x, y } => write!(f, "AND V{x:X}, V{y:X}"),
+            Self::Xor { x, y } => write!(f, "XOR V{x:X}, V{y:X}"),
+            Self::AddVxVy { x, y } => write!(f, "ADD V{x:X}, V{y:X}"),
+            Self::Sub { x, y } => write!(f, "SUB V{x:X}, V{y:X}"),
+            Self::Shr { x, y } => write!(f, "SHR V{x:X}, V{y:X}"),
+            Self::Subn { x, y } => write!(f, "SUBN V{x:X}, V{y:X}"),
+            Self::Shl { x, y } => write!(f, "SHL V{x:X}, V{y:X}"),
+            Self::SneVxVy { x, y } => write!(f, "SNE V{x:X}, V{y:X}"),
+            Self::LdI { nnn } => write!(f, "LD I, 0x{nnn:03X}"),
+            Self::JpV0 { nnn } => write!(f, "JP V0, 0x{nnn:03X}"),
+            Self::Rnd { x, kk } => write!(f, "RND V{x:X}, 0x{kk:02X}"),
+            Self::Drw { x, y, n } => write!(f, "DRW V{x:X}, V{y:X}, {n}"),
+            Self::Skp { x } => write!(f, "SKP V{x:X}"),
+            Self::Sknp { x } => write!(f, "SKNP V{x:X}"),
+            Self::LdILong => write!(f, "LD I, long"),
+            Self::Audio => write!(f, "AUDIO"),
+            Self::Plane { n } => write!(f, "PLANE {n}"),
+            Self::LdVxDt { x } => write!(f, "LD V{x:X}, DT"),
+            Self::LdVxK { x } => write!(f, "LD V{x:X}, K"),
+            Self::LdDtVx { x } => write!(f, "LD DT, V{x:X}"),
+            Self::LdStVx { x } => write!(f, "LD ST, V{x:X}"),
+            Self::AddIVx { x } => write!(f, "ADD I, V{x:X}"),
+            Self::LdFVx { x } => write!(f, "LD F, V{x:X}"),
+            Self::LdHfVx { x } => write!(f, "LD HF, V{x:X}"),
+            Self::LdBVx { x } => write!(f, "LD B, V{x:X}"),
+            Self::Pitch { x } => write!(f, "PITCH V{x:X}"),
+            Self::LdIVx { x } => write!(f, "LD [I], V{x:X}"),
+            Self::LdVxI { x } => write!(f, "LD V{x:X}, [I]"),
+            Self::LdRVx { x } => write!(f, "LD R, V{x:X}"),
+            Self::LdVxR { x } => write!(f, "LD V{x:X}, R"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn should_decode_typed_operands() {
+        assert_eq!(
+            Opcode::decode(0x6A11),
+            Ok(Opcode::LdVxByte { x: 0xA, kk: 0x11 })
+        );
+        assert_eq!(Opcode::decode(0xD125), Ok(Opcode::Drw { x: 1, y: 2, n: 5 }));
+        assert_eq!(Opcode::decode(0x00E0), Ok(Opcode::Cls));
+        assert_eq!(Opcode::decode(0x0123), Ok(Opcode::Sys { nnn: 0x123 }));
+        assert_eq!(Opcode::decode(0xF301), Ok(Opcode::Plane { n: 3 }));
+        assert_eq!(Opcode::decode(0x8008), Err(UnknownOpcode(0x8008)));
+        assert_eq!(Opcode::decode(0x9001), Err(UnknownOpcode(0x9001)));
+        assert_eq!(Opcode::decode(0xE0FF), Err(UnknownOpcode(0xE0FF)));
+    }
+    #[test]
+    fn should_show_mnemonics() {
+        let mnemonics = [
+            (0x00EE, "RET"),
+            (0x1208, "JP 0x208"),
+            (0x3A0F, "SE VA, 0x0F"),
+            (0x8A2E, "SHL VA, V2"),
+            (0xB300, "JP V0, 0x300"),
+            (0xD015, "DRW V0, V1, 5"),
+            (0xF265, "LD V2, [I]"),
+        ];
+        for (opcode, mnemonic) in mnemonics {
+            assert_eq!(Opcode::decode(opcode).unwrap().to_string(), mnemonic);
+        }
+    }
+    #[test]
+    fn should_tell_which_platform_runs_each_opcode() {
+        let extension = |opcode| Opcode::decode(opcode).unwrap().extension();
+        assert_eq!(extension(0xD125), Extension::Chip8);
+        assert_eq!(extension(0xD120), Extension::Superchip);
+        assert_eq!(extension(0xF775), Extension::Superchip);
+        assert_eq!(extension(0xF875), Extension::Xochip);
+        assert_eq!(extension(0xF000), Extension::Xochip);
+        assert_eq!(Opcode::decode(0xF000).unwrap().size(), 4);
+    }
+}
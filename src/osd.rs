@@ -0,0 +1,217 @@
+use std::time::{Duration, Instant};
+
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+pub const GLYPH_WIDTH: i32 = 3;
+pub const GLYPH_HEIGHT: i32 = 5;
+const MARGIN: i32 = 2;
+// Cells are one pixel larger than a glyph on every side, so a held key can be drawn inverted
+const CELL_WIDTH: i32 = GLYPH_WIDTH + 2;
+const CELL_HEIGHT: i32 = GLYPH_HEIGHT + 2;
+// The COSMAC VIP keypad layout
+pub const KEYPAD: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+// 3x5 glyphs, one row per byte with the leftmost pixel in bit 2
+const FONT: [(char, [u8; 5]); 50] = [
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b110, 0b001, 0b010, 0b100, 0b111]),
+    ('3', [0b110, 0b001, 0b010, 0b001, 0b110]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b110, 0b001, 0b110]),
+    ('6', [0b011, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b010, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b110]),
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('%', [0b101, 0b001, 0b010, 0b100, 0b101]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+    ('=', [0b000, 0b111, 0b000, 0b111, 0b000]),
+    ('!', [0b010, 0b010, 0b010, 0b000, 0b010]),
+    ('?', [0b110, 0b001, 0b010, 0b000, 0b010]),
+    ('(', [0b001, 0b010, 0b010, 0b010, 0b001]),
+    (')', [0b100, 0b010, 0b010, 0b010, 0b100]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+];
+
+// Lowercase letters use the uppercase glyphs, anything else missing from the font becomes a ?
+fn glyph(c: char) -> [u8; 5] {
+    let c = c.to_ascii_uppercase();
+    FONT.iter()
+        .find(|(glyph, _)| *glyph == c)
+        .or_else(|| FONT.iter().find(|(glyph, _)| *glyph == '?'))
+        .map(|(_, rows)| *rows)
+        .unwrap()
+}
+
+pub fn text_pixels(text: &str, x: i32, y: i32, pixels: &mut Vec<(i32, i32)>) {
+    for (index, c) in text.chars().enumerate() {
+        let left = x + index as i32 * (GLYPH_WIDTH + 1);
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                    pixels.push((left + column, y + row as i32));
+                }
+            }
+        }
+    }
+}
+
+// Drawn by the frontend on top of the scaled screen, never into the CHIP-8 framebuffer,
+// so nothing here can change what the program sees or cause a collision
+pub struct Osd {
+    message: Option<(String, Instant)>,
+    pub show_stats: bool,
+    pub show_keypad: bool,
+    fps: f32,
+    ips: f32,
+    keys: [bool; 16],
+}
+
+impl Osd {
+    pub fn new(show_stats: bool, show_keypad: bool) -> Self {
+        Self {
+            message: None,
+            show_stats,
+            show_keypad,
+            fps: 0.0,
+            ips: 0.0,
+            keys: [false; 16],
+        }
+    }
+    pub fn message(&mut self, message: &str, now: Instant) {
+        self.message = Some((message.to_string(), now));
+    }
+    pub fn set_stats(&mut self, fps: f32, ips: f32) {
+        self.fps = fps;
+        self.ips = ips;
+    }
+    pub fn set_keys(&mut self, keys: [bool; 16]) {
+        self.keys = keys;
+    }
+    pub fn is_visible(&self, now: Instant) -> bool {
+        self.show_stats || self.show_keypad || self.current_message(now).is_some()
+    }
+    fn current_message(&self, now: Instant) -> Option<&str> {
+        self.message
+            .as_ref()
+            .filter(|(_, shown_at)| now.duration_since(*shown_at) < MESSAGE_DURATION)
+            .map(|(message, _)| message.as_str())
+    }
+    // Lit OSD pixels in an area of `width` x `height` OSD pixels, with (0, 0) at the top left
+    pub fn pixels(&self, width: i32, height: i32, now: Instant) -> Vec<(i32, i32)> {
+        let mut pixels = Vec::new();
+        if self.show_stats {
+            let stats = format!("{:.0} FPS {:.0} IPS", self.fps, self.ips);
+            text_pixels(stats.as_str(), MARGIN, MARGIN, &mut pixels);
+        }
+        if self.show_keypad {
+            let left = width - MARGIN - 4 * (CELL_WIDTH + 1) + 1;
+            for (row, keys) in KEYPAD.iter().enumerate() {
+                for (column, key) in keys.iter().enumerate() {
+                    let x = left + column as i32 * (CELL_WIDTH + 1);
+                    let y = MARGIN + row as i32 * (CELL_HEIGHT + 1);
+                    key_pixels(*key, self.keys[*key as usize], x, y, &mut pixels);
+                }
+            }
+        }
+        if let Some(message) = self.current_message(now) {
+            text_pixels(message, MARGIN, height - MARGIN - GLYPH_HEIGHT, &mut pixels);
+        }
+        pixels
+    }
+}
+
+// A held key is drawn inverted, a lit cell with the digit cut out of it
+fn key_pixels(key: u8, held: bool, x: i32, y: i32, pixels: &mut Vec<(i32, i32)>) {
+    let mut digit = Vec::new();
+    text_pixels(format!("{key:X}").as_str(), x + 1, y + 1, &mut digit);
+    if !held {
+        pixels.append(&mut digit);
+        return;
+    }
+    for cell_y in y..y + CELL_HEIGHT {
+        for cell_x in x..x + CELL_WIDTH {
+            if !digit.contains(&(cell_x, cell_y)) {
+                pixels.push((cell_x, cell_y));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn should_draw_text_with_the_bitmap_font() {
+        let mut pixels = Vec::new();
+        text_pixels("1i", 10, 20, &mut pixels);
+        let expected_one = [(11, 20), (10, 21), (11, 21), (11, 22), (11, 23)];
+        assert!(expected_one.iter().all(|pixel| pixels.contains(pixel)));
+        // The lowercase i is an uppercase I, four pixels to the right
+        assert!(pixels.contains(&(14, 20)) && pixels.contains(&(16, 24)));
+        assert_eq!(pixels.len(), 8 + 9);
+    }
+    #[test]
+    fn should_hide_messages_after_a_while() {
+        let now = Instant::now();
+        let mut osd = Osd::new(false, false);
+        assert!(!osd.is_visible(now));
+        osd.message("Paused", now);
+        assert!(osd.is_visible(now + Duration::from_secs(1)));
+        assert!(osd.pixels(128, 64, now).iter().all(|&(_, y)| y >= 64 - 7));
+        assert!(!osd.is_visible(now + MESSAGE_DURATION));
+        assert!(osd.pixels(128, 64, now + MESSAGE_DURATION).is_empty());
+    }
+    #[test]
+    fn should_invert_held_keys_on_the_keypad() {
+        let now = Instant::now();
+        let mut osd = Osd::new(false, true);
+        let released = osd.pixels(128, 64, now).len();
+        let mut keys = [false; 16];
+        keys[0x5] = true;
+        osd.set_keys(keys);
+        let held = osd.pixels(128, 64, now).len();
+        let five = 9;
+        assert_eq!(
+            held,
+            released - five + (CELL_WIDTH * CELL_HEIGHT) as usize - five
+        );
+        assert!(osd.pixels(128, 64, now).iter().all(|&(x, _)| x < 128));
+    }
+}
@@ -0,0 +1,141 @@
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Colour {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Colour {
+    pub const fn from_rgb(rgb: u32) -> Self {
+        Self {
+            r: (rgb >> 16) as u8,
+            g: (rgb >> 8) as u8,
+            b: rgb as u8,
+        }
+    }
+    pub fn to_array(self) -> [u8; 3] {
+        [self.r, self.g, self.b]
+    }
+}
+
+impl FromStr for Colour {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let hex = input.trim().trim_start_matches('#');
+        if hex.len() != 6 {
+            return Err(format!("Colour must look like #RRGGBB: {input}"));
+        }
+        u32::from_str_radix(hex, 16)
+            .map(Self::from_rgb)
+            .map_err(|_| format!("Invalid colour: {input}"))
+    }
+}
+
+// Background, foreground, and the two extra colours used once there are two planes
+const PRESETS: [(&str, [u32; 4]); 5] = [
+    ("classic", [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555]),
+    ("green-phosphor", [0x0A1A0A, 0x33FF66, 0x1F9A3F, 0x145A28]),
+    ("amber", [0x1A0E00, 0xFFB000, 0xB37B00, 0x664600]),
+    ("lcd", [0x9BBC0F, 0x0F380F, 0x306230, 0x8BAC0F]),
+    ("octo", [0x996600, 0xFFCC00, 0xFF6600, 0x662200]),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub name: String,
+    pub colours: [Colour; 4],
+}
+
+impl Palette {
+    pub fn preset(name: &str) -> Option<Self> {
+        PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(preset, colours)| Self {
+                name: preset.to_string(),
+                colours: colours.map(Colour::from_rgb),
+            })
+    }
+    pub fn custom(foreground: Colour, background: Colour) -> Self {
+        Self {
+            name: "custom".to_string(),
+            colours: [background, foreground, foreground, foreground],
+        }
+    }
+    pub fn background(&self) -> Colour {
+        self.colours[0]
+    }
+    pub fn foreground(&self) -> Colour {
+        self.colours[1]
+    }
+    // Next preset in the list, custom palettes go back to the first one
+    pub fn next(&self) -> Self {
+        let index = PRESETS
+            .iter()
+            .position(|(preset, _)| *preset == self.name)
+            .map_or(0, |i| (i + 1) % PRESETS.len());
+        Self::preset(PRESETS[index].0).unwrap()
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::preset("classic").unwrap()
+    }
+}
+
+// Either a preset name or a custom `foreground,background` pair
+impl FromStr for Palette {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        if let Some(palette) = Self::preset(input) {
+            return Ok(palette);
+        }
+        match input.split_once(',') {
+            Some((foreground, background)) => Ok(Self::custom(
+                Colour::from_str(foreground)?,
+                Colour::from_str(background)?,
+            )),
+            None => Err(format!(
+                "No such palette: {input}. Use one of {} or #RRGGBB,#RRGGBB",
+                PRESETS.map(|(preset, _)| preset).join(", ")
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn should_parse_hex_colours() {
+        assert_eq!(
+            Colour::from_str("#FFB000"),
+            Ok(Colour {
+                r: 0xFF,
+                g: 0xB0,
+                b: 0x00
+            })
+        );
+        assert_eq!(Colour::from_str("33ff66"), Ok(Colour::from_rgb(0x33FF66)));
+        assert!(Colour::from_str("#FFF").is_err());
+    }
+    #[test]
+    fn should_parse_presets_and_custom_palettes() {
+        assert_eq!(Palette::from_str("amber").unwrap().name, "amber");
+        let palette = Palette::from_str("#33FF66,#000000").unwrap();
+        assert_eq!(palette.foreground(), Colour::from_rgb(0x33FF66));
+        assert_eq!(palette.background(), Colour::from_rgb(0x000000));
+        assert!(Palette::from_str("sepia").is_err());
+    }
+    #[test]
+    fn should_cycle_through_presets() {
+        let last = Palette::preset("octo").unwrap();
+        assert_eq!(last.next(), Palette::default());
+        let custom = Palette::from_str("#FFFFFF,#000000").unwrap();
+        assert_eq!(custom.next(), Palette::default());
+    }
+}
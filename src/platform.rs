@@ -0,0 +1,38 @@
+use crate::memory::{Layout, MAX_MEMORY_SIZE};
+use clap::ValueEnum;
+
+// Machines that kept their interpreter, and so the start of programs, in different places
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Platform {
+    // The COSMAC VIP, and every interpreter after it, load programs at 0x200
+    #[default]
+    Vip,
+    Eti660,
+    // A memory image from address 0, which brings its own font if it needs one
+    Raw,
+    // 64K of memory, though the XO-CHIP opcodes still stop with an error
+    Xochip,
+}
+
+impl Platform {
+    pub fn layout(self) -> Layout {
+        match self {
+            Self::Vip => Layout::default(),
+            Self::Eti660 => Layout {
+                load_address: 0x600,
+                entry: 0x600,
+                ..Layout::default()
+            },
+            Self::Raw => Layout {
+                load_address: 0,
+                entry: 0,
+                overwrite_font: true,
+                ..Layout::default()
+            },
+            Self::Xochip => Layout {
+                memory_size: MAX_MEMORY_SIZE,
+                ..Layout::default()
+            },
+        }
+    }
+}
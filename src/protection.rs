@@ -0,0 +1,140 @@
+use std::fmt;
+use std::str::FromStr;
+
+// What strict mode does about each kind of violation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Action {
+    #[default]
+    Off,
+    Warn,
+    // Pauses the emulator, or goes back to the debugger prompt
+    Break,
+    // Stops the emulator with an error
+    Error,
+}
+
+impl FromStr for Action {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "off" => Ok(Self::Off),
+            "warn" => Ok(Self::Warn),
+            "break" => Ok(Self::Break),
+            "error" => Ok(Self::Error),
+            _ => Err(format!(
+                "No such action: {input}, expected off, warn, break or error"
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Violation {
+    // A write below the load address, into the interpreter and the font
+    LowWrite(usize),
+    // A read or write past the end of memory, which wraps around
+    OutOfBounds(usize),
+    // An instruction fetched from bytes that neither the ROM nor the program ever wrote
+    UninitializedExecution(usize),
+    // A write into bytes that already ran as an instruction
+    CodeWrite(usize),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LowWrite(address) => write!(f, "write below the program at 0x{address:03X}"),
+            Self::OutOfBounds(address) => {
+                write!(f, "access past the end of memory at 0x{address:X}")
+            }
+            Self::UninitializedExecution(address) => {
+                write!(f, "execution of bytes never loaded at 0x{address:03X}")
+            }
+            Self::CodeWrite(address) => write!(f, "write into code at 0x{address:03X}"),
+        }
+    }
+}
+
+// Everything off unless --strict, which warns about everything unless told otherwise
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Protection {
+    pub low_write: Action,
+    pub out_of_bounds: Action,
+    pub uninitialized_execution: Action,
+    pub code_write: Action,
+}
+
+impl Protection {
+    pub fn action(&self, violation: Violation) -> Action {
+        match violation {
+            Violation::LowWrite(_) => self.low_write,
+            Violation::OutOfBounds(_) => self.out_of_bounds,
+            Violation::UninitializedExecution(_) => self.uninitialized_execution,
+            Violation::CodeWrite(_) => self.code_write,
+        }
+    }
+}
+
+// Comma separated actions for every violation, like `warn`, or for one, like `code-write=break`
+impl FromStr for Protection {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut protection = Self {
+            low_write: Action::Warn,
+            out_of_bounds: Action::Warn,
+            uninitialized_execution: Action::Warn,
+            code_write: Action::Warn,
+        };
+        for item in input
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            let Some((violation, action)) = item.split_once('=') else {
+                let action = item.parse()?;
+                protection = Self {
+                    low_write: action,
+                    out_of_bounds: action,
+                    uninitialized_execution: action,
+                    code_write: action,
+                };
+                continue;
+            };
+            let action = action.trim().parse()?;
+            match violation.trim() {
+                "low-write" => protection.low_write = action,
+                "out-of-bounds" => protection.out_of_bounds = action,
+                "uninitialized" => protection.uninitialized_execution = action,
+                "code-write" => protection.code_write = action,
+                violation => {
+                    return Err(format!(
+                        "No such violation: {violation}, expected low-write, out-of-bounds, uninitialized or code-write"
+                    ))
+                }
+            }
+        }
+        Ok(protection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn should_parse_actions_for_every_violation_or_one() {
+        let protection: Protection = "".parse().unwrap();
+        assert_eq!(protection.action(Violation::CodeWrite(0x200)), Action::Warn);
+        let protection: Protection = "error, code-write=break,low-write=off".parse().unwrap();
+        assert_eq!(
+            protection.action(Violation::OutOfBounds(0x1000)),
+            Action::Error
+        );
+        assert_eq!(
+            protection.action(Violation::CodeWrite(0x200)),
+            Action::Break
+        );
+        assert_eq!(protection.action(Violation::LowWrite(0x100)), Action::Off);
+        assert!("code-write=explode".parse::<Protection>().is_err());
+        assert!("stack=warn".parse::<Protection>().is_err());
+    }
+}
@@ -0,0 +1,63 @@
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum QuirksProfile {
+    // What this emulator always did, following Cowgod's reference
+    #[default]
+    Cowgod,
+    Vip,
+    Schip,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    // 8xy1, 8xy2 and 8xy3 reset VF to 0
+    pub vf_reset: bool,
+    // Fx55 and Fx65 leave I pointing past the last register
+    pub memory_increment: bool,
+    // 8xy6 and 8xyE shift Vy into Vx instead of shifting Vx in place
+    pub shift_uses_vy: bool,
+    // Bnnn behaves as Bxnn, jumping to xnn + Vx
+    pub jump_uses_vx: bool,
+    // Dxyn clips sprites at the screen edges instead of wrapping them
+    pub clipping: bool,
+    // Fx0A waits for the key to be released before storing it, instead of storing it on press
+    pub key_wait_release: bool,
+}
+
+impl Quirks {
+    pub fn new(profile: QuirksProfile) -> Self {
+        match profile {
+            QuirksProfile::Cowgod => Self {
+                vf_reset: false,
+                memory_increment: false,
+                shift_uses_vy: false,
+                jump_uses_vx: false,
+                clipping: false,
+                key_wait_release: false,
+            },
+            QuirksProfile::Vip => Self {
+                vf_reset: true,
+                memory_increment: true,
+                shift_uses_vy: true,
+                jump_uses_vx: false,
+                clipping: true,
+                key_wait_release: true,
+            },
+            QuirksProfile::Schip => Self {
+                vf_reset: false,
+                memory_increment: false,
+                shift_uses_vy: false,
+                jump_uses_vx: true,
+                clipping: true,
+                key_wait_release: true,
+            },
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::new(QuirksProfile::default())
+    }
+}
@@ -0,0 +1,355 @@
+use crate::audio::{AudioSink, Tone, WavAudio};
+use crate::display::HEIGHT;
+use crate::display::WIDTH;
+use crate::framebuffer::Framebuffer;
+use crate::palette::{Colour, Palette};
+use crate::screenshot;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const FRAME_RATE: u64 = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordFormat {
+    Gif,
+    Apng,
+    Y4m,
+}
+
+impl RecordFormat {
+    // Picked from the extension, `-` streams y4m to stdout
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        if path == Path::new("-") {
+            return Ok(Self::Y4m);
+        }
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("gif") => Ok(Self::Gif),
+            Some("png") | Some("apng") => Ok(Self::Apng),
+            Some("y4m") => Ok(Self::Y4m),
+            _ => Err(format!(
+                "Can't record to {}, use a .gif, .png, .apng or .y4m file",
+                path.display()
+            )),
+        }
+    }
+}
+
+enum Output {
+    // Runs of identical frames become a single longer frame, only where each run started is kept
+    Gif {
+        encoder: Box<gif::Encoder<BufWriter<File>>>,
+        pending: Option<(Framebuffer, u64)>,
+    },
+    // APNG wants the number of frames up front, so frames are kept until the recording stops
+    Apng {
+        frames: Vec<(Framebuffer, u64)>,
+    },
+    Y4m {
+        video: Box<dyn Write>,
+        colours: [[u8; 3]; 2],
+        audio: Option<WavAudio>,
+    },
+}
+
+// Records one image per emulated frame, so the timing follows the 60 Hz clock and not the wall clock
+pub struct Recorder {
+    path: PathBuf,
+    palette: Palette,
+    scale: usize,
+    frames: u64,
+    output: Output,
+}
+
+impl Recorder {
+    // `audio` is where the buzzer goes for y4m, which has no audio track of its own,
+    // next to the video with a .wav extension unless the video goes to stdout
+    pub fn create(
+        path: &Path,
+        audio: Option<&Path>,
+        tone: Tone,
+        palette: Palette,
+        scale: usize,
+    ) -> Result<Self, Box<dyn std::error::Error + 'static>> {
+        let output = match RecordFormat::from_path(path)? {
+            RecordFormat::Gif => {
+                let file = File::create(path).map_err(|e| format!("{e}: {}", path.display()))?;
+                let mut global_palette = palette.background().to_array().to_vec();
+                global_palette.extend_from_slice(&palette.foreground().to_array());
+                let mut encoder = gif::Encoder::new(
+                    BufWriter::new(file),
+                    (WIDTH * scale) as u16,
+                    (HEIGHT * scale) as u16,
+                    &global_palette,
+                )?;
+                encoder.set_repeat(gif::Repeat::Infinite)?;
+                Output::Gif {
+                    encoder: Box::new(encoder),
+                    pending: None,
+                }
+            }
+            RecordFormat::Apng => Output::Apng { frames: Vec::new() },
+            RecordFormat::Y4m => {
+                let mut video: Box<dyn Write> = if path == Path::new("-") {
+                    Box::new(io::stdout())
+                } else {
+                    let file =
+                        File::create(path).map_err(|e| format!("{e}: {}", path.display()))?;
+                    Box::new(BufWriter::new(file))
+                };
+                writeln!(
+                    video,
+                    "YUV4MPEG2 W{} H{} F{FRAME_RATE}:1 Ip A1:1 C444",
+                    WIDTH * scale,
+                    HEIGHT * scale
+                )?;
+                let audio = match audio {
+                    Some(audio) => Some(WavAudio::create(audio, tone)?),
+                    None if path != Path::new("-") => {
+                        Some(WavAudio::create(&path.with_extension("wav"), tone)?)
+                    }
+                    None => None,
+                };
+                Output::Y4m {
+                    video,
+                    colours: [to_yuv(palette.background()), to_yuv(palette.foreground())],
+                    audio,
+                }
+            }
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            palette,
+            scale,
+            frames: 0,
+            output,
+        })
+    }
+    pub fn frame(
+        &mut self,
+        framebuffer: &Framebuffer,
+        buzzer_on: bool,
+    ) -> Result<(), Box<dyn std::error::Error + 'static>> {
+        match &mut self.output {
+            Output::Gif { encoder, pending } => {
+                if pending.as_ref().is_none_or(|(last, _)| last != framebuffer) {
+                    if let Some((last, start)) = pending.take() {
+                        write_gif_frame(encoder, &last, self.scale, gif_delay(start, self.frames))?;
+                    }
+                    *pending = Some((framebuffer.clone(), self.frames));
+                }
+            }
+            Output::Apng { frames } => {
+                if frames.last().is_none_or(|(last, _)| last != framebuffer) {
+                    frames.push((framebuffer.clone(), self.frames));
+                }
+            }
+            Output::Y4m {
+                video,
+                colours,
+                audio,
+            } => {
+                video.write_all(b"FRAME\n")?;
+                let colours = *colours;
+                let indices = to_indices(framebuffer, self.scale);
+                let planes: Vec<u8> = (0..3)
+                    .flat_map(|plane| {
+                        indices
+                            .iter()
+                            .map(move |&index| colours[index as usize][plane])
+                    })
+                    .collect();
+                video.write_all(&planes)?;
+                if let Some(audio) = audio {
+                    audio.frame(buzzer_on)?;
+                }
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+    // Writes whatever is still buffered and returns where the recording went
+    pub fn finish(self) -> Result<PathBuf, Box<dyn std::error::Error + 'static>> {
+        match self.output {
+            Output::Gif {
+                mut encoder,
+                pending,
+            } => {
+                if let Some((last, start)) = pending {
+                    write_gif_frame(
+                        &mut encoder,
+                        &last,
+                        self.scale,
+                        gif_delay(start, self.frames),
+                    )?;
+                }
+                encoder.into_inner()?.flush()?;
+            }
+            Output::Apng { frames } => {
+                if frames.is_empty() {
+                    return Err("No frames were recorded".into());
+                }
+                let file = File::create(&self.path)
+                    .map_err(|e| format!("{e}: {}", self.path.display()))?;
+                let mut encoder = png::Encoder::new(
+                    BufWriter::new(file),
+                    (WIDTH * self.scale) as u32,
+                    (HEIGHT * self.scale) as u32,
+                );
+                encoder.set_color(png::ColorType::Rgb);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(frames.len() as u32, 0)?;
+                let mut writer = encoder.write_header()?;
+                for (index, (framebuffer, start)) in frames.iter().enumerate() {
+                    let end = frames.get(index + 1).map_or(self.frames, |(_, end)| *end);
+                    let length = (end - start).min(u16::MAX as u64) as u16;
+                    writer.set_frame_delay(length, FRAME_RATE as u16)?;
+                    writer.write_image_data(&screenshot::to_rgb(
+                        framebuffer,
+                        &self.palette,
+                        self.scale,
+                    ))?;
+                }
+                writer.finish()?;
+            }
+            Output::Y4m {
+                mut video, audio, ..
+            } => {
+                video.flush()?;
+                if let Some(mut audio) = audio {
+                    audio.finish()?;
+                }
+            }
+        }
+        Ok(self.path)
+    }
+}
+
+// GIF delays are in hundredths of a second, so each frame ends on the centisecond
+// closest to where the 60 Hz clock is, instead of every frame rounding down to 1/50 s
+fn gif_delay(start: u64, end: u64) -> u16 {
+    let centiseconds = |frame: u64| (frame * 100 + FRAME_RATE / 2) / FRAME_RATE;
+    (centiseconds(end) - centiseconds(start)).min(u16::MAX as u64) as u16
+}
+
+fn write_gif_frame(
+    encoder: &mut gif::Encoder<BufWriter<File>>,
+    framebuffer: &Framebuffer,
+    scale: usize,
+    delay: u16,
+) -> Result<(), gif::EncodingError> {
+    let frame = gif::Frame {
+        width: (WIDTH * scale) as u16,
+        height: (HEIGHT * scale) as u16,
+        buffer: Cow::Owned(to_indices(framebuffer, scale)),
+        delay,
+        ..gif::Frame::default()
+    };
+    encoder.write_frame(&frame)
+}
+
+// 0 for the background and 1 for the foreground, each pixel scaled to a `scale` square
+fn to_indices(framebuffer: &Framebuffer, scale: usize) -> Vec<u8> {
+    let mut indices = Vec::with_capacity(WIDTH * HEIGHT * scale * scale);
+    for y in 0..HEIGHT * scale {
+        for x in 0..WIDTH * scale {
+            indices.push(framebuffer.is_lit(x / scale, y / scale) as u8);
+        }
+    }
+    indices
+}
+
+// BT.601 limited range, what ffmpeg assumes for y4m
+fn to_yuv(colour: Colour) -> [u8; 3] {
+    let (r, g, b) = (colour.r as f32, colour.g as f32, colour.b as f32);
+    [
+        16.0 + 0.257 * r + 0.504 * g + 0.098 * b,
+        128.0 - 0.148 * r - 0.291 * g + 0.439 * b,
+        128.0 + 0.439 * r - 0.368 * g - 0.071 * b,
+    ]
+    .map(|channel| channel.round() as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    fn lit_framebuffer() -> Framebuffer {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.draw_row(0, 0, 0xFF, false);
+        framebuffer
+    }
+    #[test]
+    fn should_pick_format_from_extension() {
+        assert_eq!(
+            RecordFormat::from_path(Path::new("a.GIF")),
+            Ok(RecordFormat::Gif)
+        );
+        assert_eq!(
+            RecordFormat::from_path(Path::new("a.apng")),
+            Ok(RecordFormat::Apng)
+        );
+        assert_eq!(
+            RecordFormat::from_path(Path::new("-")),
+            Ok(RecordFormat::Y4m)
+        );
+        assert!(RecordFormat::from_path(Path::new("a.mp4")).is_err());
+    }
+    #[test]
+    fn should_keep_gif_delays_in_step_with_60_hz() {
+        let delays: Vec<u16> = (0..6).map(|frame| gif_delay(frame, frame + 1)).collect();
+        assert_eq!(delays, [2, 1, 2, 2, 1, 2]);
+        assert_eq!(
+            (0..60)
+                .map(|frame| gif_delay(frame, frame + 1))
+                .sum::<u16>(),
+            100
+        );
+    }
+    #[test]
+    fn should_merge_identical_gif_frames() {
+        let path = env::temp_dir().join("chip-8-emulator-recorder-test.gif");
+        let mut recorder =
+            Recorder::create(&path, None, Tone::default(), Palette::default(), 1).unwrap();
+        for _ in 0..3 {
+            recorder.frame(&Framebuffer::new(), false).unwrap();
+        }
+        recorder.frame(&lit_framebuffer(), false).unwrap();
+        recorder.finish().unwrap();
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(File::open(&path).unwrap())
+            .unwrap();
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        assert_eq!(delays, [5, 2]);
+        let _ = fs::remove_file(path);
+    }
+    #[test]
+    fn should_write_a_y4m_frame_and_735_samples_per_emulated_frame() {
+        let video = env::temp_dir().join("chip-8-emulator-recorder-test.y4m");
+        let audio = env::temp_dir().join("chip-8-emulator-recorder-test.wav");
+        let mut recorder =
+            Recorder::create(&video, Some(&audio), Tone::default(), Palette::default(), 2).unwrap();
+        recorder.frame(&lit_framebuffer(), true).unwrap();
+        recorder.frame(&Framebuffer::new(), false).unwrap();
+        recorder.finish().unwrap();
+        let header = "YUV4MPEG2 W128 H64 F60:1 Ip A1:1 C444\n";
+        let bytes = fs::read(&video).unwrap();
+        assert!(bytes.starts_with(header.as_bytes()));
+        assert_eq!(
+            bytes.len(),
+            header.len() + 2 * ("FRAME\n".len() + 128 * 64 * 3)
+        );
+        let reader = hound::WavReader::open(&audio).unwrap();
+        assert_eq!(reader.duration(), 2 * 735);
+        let _ = fs::remove_file(video);
+        let _ = fs::remove_file(audio);
+    }
+}
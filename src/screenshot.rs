@@ -0,0 +1,80 @@
+use crate::display::HEIGHT;
+use crate::display::WIDTH;
+use crate::framebuffer::Framebuffer;
+use crate::palette::Palette;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// RGB24 bytes of the framebuffer in the palette colours, each pixel scaled to a `scale` square
+pub fn to_rgb(framebuffer: &Framebuffer, palette: &Palette, scale: usize) -> Vec<u8> {
+    let foreground = palette.foreground().to_array();
+    let background = palette.background().to_array();
+    let mut rgb = Vec::with_capacity(WIDTH * HEIGHT * scale * scale * 3);
+    for y in 0..HEIGHT * scale {
+        for x in 0..WIDTH * scale {
+            if framebuffer.is_lit(x / scale, y / scale) {
+                rgb.extend_from_slice(&foreground);
+            } else {
+                rgb.extend_from_slice(&background);
+            }
+        }
+    }
+    rgb
+}
+
+pub fn save_png(
+    framebuffer: &Framebuffer,
+    palette: &Palette,
+    scale: usize,
+    path: &Path,
+) -> Result<(), Box<dyn std::error::Error + 'static>> {
+    let file = File::create(path).map_err(|e| format!("{e}: {}", path.display()))?;
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        (WIDTH * scale) as u32,
+        (HEIGHT * scale) as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&to_rgb(framebuffer, palette, scale))?;
+    Ok(())
+}
+
+// e.g. screenshot-1737500000000.png, so files saved in a row never overwrite each other
+pub fn timestamped_path(prefix: &str, extension: &str) -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis());
+    PathBuf::from(format!("{prefix}-{millis}.{extension}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    #[test]
+    fn should_scale_pixels_in_palette_colours() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.draw_row(0, 0, 0b1000_0000, false);
+        let palette = Palette::preset("amber").unwrap();
+        let rgb = to_rgb(&framebuffer, &palette, 2);
+        assert_eq!(rgb.len(), WIDTH * 2 * HEIGHT * 2 * 3);
+        let pixel = |x: usize, y: usize| &rgb[(y * WIDTH * 2 + x) * 3..][..3];
+        assert_eq!(pixel(1, 1), palette.foreground().to_array());
+        assert_eq!(pixel(2, 0), palette.background().to_array());
+        assert_eq!(pixel(0, 2), palette.background().to_array());
+    }
+    #[test]
+    fn should_write_png_with_scaled_size() {
+        let path = env::temp_dir().join("chip-8-emulator-screenshot-test.png");
+        save_png(&Framebuffer::new(), &Palette::default(), 3, &path).unwrap();
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let reader = decoder.read_info().unwrap();
+        assert_eq!(reader.info().width, WIDTH as u32 * 3);
+        assert_eq!(reader.info().height, HEIGHT as u32 * 3);
+        let _ = std::fs::remove_file(path);
+    }
+}
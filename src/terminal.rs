@@ -0,0 +1,314 @@
+use crate::display::HEIGHT;
+use crate::display::WIDTH;
+use crate::display::{DisplayOptions, DisplayTrait, PresentMode, Status};
+use crate::framebuffer::Framebuffer;
+use crate::hotkeys::Hotkeys;
+use crate::input::{FrontendEvent, InputSource};
+use crate::keyboard::Keyboard;
+use crate::keymap::Keymap;
+use crate::palette::{Colour, Palette};
+use clap::ValueEnum;
+use crossterm::cursor::{Hide, MoveTo, RestorePosition, SavePosition, Show};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{Color, Colors, Print, ResetColor, SetColors};
+use crossterm::terminal::{
+    self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, SetTitle,
+};
+use crossterm::{execute, queue};
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Glyphs {
+    // 1x2 pixels per cell, 64x16 cells
+    #[default]
+    HalfBlock,
+    // 2x4 pixels per cell, 32x8 cells
+    Braille,
+}
+
+pub struct TerminalDisplay {
+    title: String,
+    glyphs: Glyphs,
+    palette: Palette,
+    present_mode: PresentMode,
+    rows: Vec<String>,
+    // Whether the debugger prompt scrolls under the screen
+    debugger: bool,
+    stdout: Stdout,
+}
+
+impl TerminalDisplay {
+    pub fn new(glyphs: Glyphs, options: DisplayOptions) -> io::Result<Self> {
+        let mut stdout = io::stdout();
+        execute!(
+            stdout,
+            EnterAlternateScreen,
+            Hide,
+            Clear(ClearType::All),
+            SetTitle(options.title.as_str())
+        )?;
+        Ok(Self {
+            title: options.title,
+            glyphs,
+            palette: options.palette,
+            present_mode: options.present_mode,
+            rows: Vec::new(),
+            debugger: false,
+            stdout,
+        })
+    }
+    // Keeps the screen on the top rows and scrolls only the lines below it, where the
+    // debugger prompt and its output go
+    pub fn set_debugger(&mut self) -> io::Result<()> {
+        let rows = match self.glyphs {
+            Glyphs::HalfBlock => HEIGHT / 2,
+            Glyphs::Braille => HEIGHT / 4,
+        } as u16;
+        let (_, height) = terminal::size()?;
+        if height < rows + 2 {
+            return Err(io::Error::other(format!(
+                "{height} rows are too few for the screen and the debugger"
+            )));
+        }
+        execute!(
+            self.stdout,
+            Show,
+            Print(format!("\x1b[{};{}r", rows + 2, height)),
+            MoveTo(0, height - 1)
+        )?;
+        self.debugger = true;
+        Ok(())
+    }
+    fn present(&mut self, framebuffer: &Framebuffer) {
+        let rows = self.render(framebuffer);
+        let colours = Colors::new(
+            to_terminal_colour(self.palette.foreground()),
+            to_terminal_colour(self.palette.background()),
+        );
+        if self.debugger {
+            let _ = queue!(self.stdout, SavePosition);
+        }
+        let _ = queue!(self.stdout, SetColors(colours));
+        // Only rows that changed since the last draw are sent to the terminal
+        for (i, row) in rows.iter().enumerate() {
+            if self.rows.get(i) != Some(row) {
+                let _ = queue!(self.stdout, MoveTo(0, i as u16), Print(row));
+            }
+        }
+        if self.debugger {
+            let _ = queue!(self.stdout, ResetColor, RestorePosition);
+        }
+        let _ = self.stdout.flush();
+        self.rows = rows;
+    }
+    fn render(&self, framebuffer: &Framebuffer) -> Vec<String> {
+        match self.glyphs {
+            Glyphs::HalfBlock => (0..HEIGHT / 2)
+                .map(|row| {
+                    (0..WIDTH)
+                        .map(|x| {
+                            match (
+                                framebuffer.is_lit(x, row * 2),
+                                framebuffer.is_lit(x, row * 2 + 1),
+                            ) {
+                                (true, true) => '█',
+                                (true, false) => '▀',
+                                (false, true) => '▄',
+                                (false, false) => ' ',
+                            }
+                        })
+                        .collect()
+                })
+                .collect(),
+            Glyphs::Braille => (0..HEIGHT / 4)
+                .map(|row| {
+                    (0..WIDTH / 2)
+                        .map(|column| {
+                            let dots = BRAILLE_DOTS
+                                .iter()
+                                .filter(|(dx, dy, _)| {
+                                    framebuffer.is_lit(column * 2 + dx, row * 4 + dy)
+                                })
+                                .fold(0, |dots, (_, _, bit)| dots | bit);
+                            char::from_u32(0x2800 + dots).unwrap_or(' ')
+                        })
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+}
+
+// Offset inside the 2x4 cell and the bit of the matching Braille dot
+const BRAILLE_DOTS: [(usize, usize, u32); 8] = [
+    (0, 0, 0x01),
+    (0, 1, 0x02),
+    (0, 2, 0x04),
+    (1, 0, 0x08),
+    (1, 1, 0x10),
+    (1, 2, 0x20),
+    (0, 3, 0x40),
+    (1, 3, 0x80),
+];
+
+impl Drop for TerminalDisplay {
+    fn drop(&mut self) {
+        if self.debugger {
+            let _ = execute!(self.stdout, Print("\x1b[r"));
+        }
+        let _ = execute!(self.stdout, ResetColor, Show, LeaveAlternateScreen);
+    }
+}
+
+impl DisplayTrait for TerminalDisplay {
+    fn draw(&mut self, framebuffer: &Framebuffer) -> bool {
+        if self.present_mode == PresentMode::Frame {
+            return false;
+        }
+        self.present(framebuffer);
+        true
+    }
+    fn end_frame(&mut self, framebuffer: &Framebuffer) {
+        if self.present_mode == PresentMode::Frame {
+            self.present(framebuffer);
+        }
+    }
+    fn handle_event(&mut self, event: FrontendEvent) {
+        match event {
+            FrontendEvent::CyclePalette => {
+                self.palette = self.palette.next();
+                // Every row has to be redrawn with the new colours
+                self.rows.clear();
+            }
+            FrontendEvent::TogglePresentMode => self.present_mode = self.present_mode.toggle(),
+            _ => {}
+        }
+    }
+    fn show_status(&mut self, status: Status) {
+        let _ = execute!(self.stdout, SetTitle(status.title(self.title.as_str())));
+    }
+    fn palette(&self) -> Palette {
+        self.palette.clone()
+    }
+}
+
+fn to_terminal_colour(colour: Colour) -> Color {
+    Color::Rgb {
+        r: colour.r,
+        g: colour.g,
+        b: colour.b,
+    }
+}
+
+pub struct TerminalInput {
+    release_after: Duration,
+    pressed_at: [Option<Instant>; 16],
+    enhanced: bool,
+    hotkeys: Hotkeys,
+    keymap: Keymap,
+}
+
+impl TerminalInput {
+    pub fn new(release_after: Duration, hotkeys: Hotkeys, keymap: Keymap) -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        // Most terminals never report key releases, so keys are released
+        // after `release_after` unless the terminal supports the kitty protocol
+        let enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced {
+            execute!(
+                io::stdout(),
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        Ok(Self {
+            release_after,
+            pressed_at: [None; 16],
+            enhanced,
+            hotkeys,
+            keymap,
+        })
+    }
+}
+
+impl InputSource for TerminalInput {
+    fn poll(&mut self, _frame: u64, keyboard: &mut Keyboard) -> Vec<FrontendEvent> {
+        let mut frontend_events = Vec::new();
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            let Ok(Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind,
+                ..
+            })) = event::read()
+            else {
+                continue;
+            };
+            if let Some(event) = key_name(code).and_then(|name| self.hotkeys.event(name.as_str())) {
+                if kind == KeyEventKind::Press {
+                    frontend_events.push(event);
+                }
+                continue;
+            }
+            match code {
+                KeyCode::Esc => frontend_events.push(FrontendEvent::Quit),
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                    frontend_events.push(FrontendEvent::Quit)
+                }
+                code => {
+                    if let Some(key) =
+                        key_name(code).and_then(|name| self.keymap.key(name.as_str()))
+                    {
+                        if kind == KeyEventKind::Release {
+                            keyboard.release(key);
+                            self.pressed_at[key as usize] = None;
+                        } else {
+                            keyboard.press(key);
+                            self.pressed_at[key as usize] = Some(Instant::now());
+                        }
+                    }
+                }
+            }
+        }
+        if !self.enhanced {
+            for key in 0..16 {
+                if let Some(pressed_at) = self.pressed_at[key] {
+                    if pressed_at.elapsed() >= self.release_after {
+                        keyboard.release(key as u8);
+                        self.pressed_at[key] = None;
+                    }
+                }
+            }
+        }
+        frontend_events
+    }
+}
+
+// The SDL name of a key, which is what hotkeys are bound to
+fn key_name(code: KeyCode) -> Option<String> {
+    match code {
+        KeyCode::F(n) => Some(format!("F{n}")),
+        KeyCode::Char(' ') => Some("Space".to_string()),
+        KeyCode::Char(c) => Some(c.to_ascii_uppercase().to_string()),
+        KeyCode::Backspace => Some("Backspace".to_string()),
+        KeyCode::Tab => Some("Tab".to_string()),
+        KeyCode::Enter => Some("Return".to_string()),
+        KeyCode::Up => Some("Up".to_string()),
+        KeyCode::Down => Some("Down".to_string()),
+        KeyCode::Left => Some("Left".to_string()),
+        KeyCode::Right => Some("Right".to_string()),
+        _ => None,
+    }
+}
+
+impl Drop for TerminalInput {
+    fn drop(&mut self) {
+        if self.enhanced {
+            let _ = execute!(io::stdout(), PopKeyboardEnhancementFlags);
+        }
+        let _ = terminal::disable_raw_mode();
+    }
+}
@@ -0,0 +1,257 @@
+use crate::display::{HEIGHT, WIDTH};
+use crate::keyboard::Keyboard;
+use crate::osd::KEYPAD;
+use clap::ValueEnum;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::mouse::MouseButton;
+use sdl2::rect::Rect;
+
+// SDL also sends mouse events for touches, from this mouse id, which fingers already cover
+const TOUCH_MOUSE_ID: u32 = u32::MAX;
+
+// Where the clickable keypad goes in the window
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TouchKeypad {
+    Beside,
+    Under,
+}
+
+// A square keypad as tall as the screen beside it, or as tall as the screen above it
+pub fn window_size(scale: u32, touch_keypad: Option<TouchKeypad>) -> (u32, u32) {
+    let (width, height) = (WIDTH as u32 * scale, HEIGHT as u32 * scale);
+    match touch_keypad {
+        None => (width, height),
+        Some(TouchKeypad::Beside) => (width + height, height),
+        Some(TouchKeypad::Under) => (width, height * 2),
+    }
+}
+
+// The area left for the screen and the largest square keypad that fits in the rest
+pub fn split((width, height): (u32, u32), touch_keypad: TouchKeypad) -> (Rect, Rect) {
+    match touch_keypad {
+        TouchKeypad::Beside => {
+            let screen_width = width * 2 / 3;
+            let keypad_width = width - screen_width;
+            let side = keypad_width.min(height);
+            let keypad = Rect::new(
+                (screen_width + (keypad_width - side) / 2) as i32,
+                ((height - side) / 2) as i32,
+                side,
+                side,
+            );
+            (Rect::new(0, 0, screen_width.max(1), height), keypad)
+        }
+        TouchKeypad::Under => {
+            let screen_height = height / 2;
+            let keypad_height = height - screen_height;
+            let side = keypad_height.min(width);
+            let keypad = Rect::new(
+                ((width - side) / 2) as i32,
+                (screen_height + (keypad_height - side) / 2) as i32,
+                side,
+                side,
+            );
+            (Rect::new(0, 0, width, screen_height.max(1)), keypad)
+        }
+    }
+}
+
+// Each key of the keypad with its cell, in keypad order
+pub fn cells(keypad: Rect) -> impl Iterator<Item = (u8, Rect)> {
+    let size = keypad.width() / 4;
+    KEYPAD.into_iter().enumerate().flat_map(move |(row, keys)| {
+        keys.into_iter().enumerate().map(move |(column, key)| {
+            let x = keypad.x() + (column as u32 * size) as i32;
+            let y = keypad.y() + (row as u32 * size) as i32;
+            (key, Rect::new(x, y, size.max(1), size.max(1)))
+        })
+    })
+}
+
+fn key_at(keypad: Rect, x: i32, y: i32) -> Option<u8> {
+    cells(keypad)
+        .find(|(_, cell)| cell.contains_point((x, y)))
+        .map(|(key, _)| key)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Pointer {
+    Mouse,
+    Finger(i64, i64),
+}
+
+// Presses keys for the mouse and for every finger on the clickable keypad. A key stays
+// down while any pointer holds it, so fingers can play chords and slide from key to key
+pub struct Touchpad {
+    touch_keypad: Option<TouchKeypad>,
+    window_size: (u32, u32),
+    held: Vec<(Pointer, u8)>,
+}
+
+impl Touchpad {
+    pub fn new(touch_keypad: Option<TouchKeypad>, window_size: (u32, u32)) -> Self {
+        Self {
+            touch_keypad,
+            window_size,
+            held: Vec::new(),
+        }
+    }
+    pub fn handle_event(&mut self, event: &Event, keyboard: &mut Keyboard) {
+        if self.touch_keypad.is_none() {
+            return;
+        }
+        match *event {
+            Event::Window {
+                win_event: WindowEvent::SizeChanged(width, height),
+                ..
+            } => self.window_size = (width.max(1) as u32, height.max(1) as u32),
+            Event::MouseButtonDown {
+                which,
+                mouse_btn: MouseButton::Left,
+                x,
+                y,
+                ..
+            } if which != TOUCH_MOUSE_ID => self.point(Pointer::Mouse, x, y, keyboard),
+            Event::MouseMotion { which, x, y, .. }
+                if which != TOUCH_MOUSE_ID && self.is_held(Pointer::Mouse) =>
+            {
+                self.point(Pointer::Mouse, x, y, keyboard)
+            }
+            Event::MouseButtonUp {
+                mouse_btn: MouseButton::Left,
+                ..
+            } => self.lift(Pointer::Mouse, keyboard),
+            Event::FingerDown {
+                touch_id,
+                finger_id,
+                x,
+                y,
+                ..
+            } => {
+                let (x, y) = self.to_window(x, y);
+                self.point(Pointer::Finger(touch_id, finger_id), x, y, keyboard)
+            }
+            Event::FingerMotion {
+                touch_id,
+                finger_id,
+                x,
+                y,
+                ..
+            } if self.is_held(Pointer::Finger(touch_id, finger_id)) => {
+                let (x, y) = self.to_window(x, y);
+                self.point(Pointer::Finger(touch_id, finger_id), x, y, keyboard)
+            }
+            Event::FingerUp {
+                touch_id,
+                finger_id,
+                ..
+            } => self.lift(Pointer::Finger(touch_id, finger_id), keyboard),
+            _ => {}
+        }
+    }
+    // Touches come from 0 to 1 across the window
+    fn to_window(&self, x: f32, y: f32) -> (i32, i32) {
+        (
+            (x * self.window_size.0 as f32) as i32,
+            (y * self.window_size.1 as f32) as i32,
+        )
+    }
+    fn is_held(&self, pointer: Pointer) -> bool {
+        self.held.iter().any(|(other, _)| *other == pointer)
+    }
+    // A pointer going down, or moving while down, onto a key or off the keypad
+    fn point(&mut self, pointer: Pointer, x: i32, y: i32, keyboard: &mut Keyboard) {
+        let Some(touch_keypad) = self.touch_keypad else {
+            return;
+        };
+        let (_, keypad) = split(self.window_size, touch_keypad);
+        let key = key_at(keypad, x, y);
+        if self
+            .held
+            .iter()
+            .any(|(other, held)| *other == pointer && Some(*held) == key)
+        {
+            return;
+        }
+        self.lift(pointer, keyboard);
+        if let Some(key) = key {
+            self.held.push((pointer, key));
+            keyboard.press(key);
+        }
+    }
+    fn lift(&mut self, pointer: Pointer, keyboard: &mut Keyboard) {
+        let Some(index) = self.held.iter().position(|(other, _)| *other == pointer) else {
+            return;
+        };
+        let (_, key) = self.held.remove(index);
+        if !self.held.iter().any(|(_, other)| *other == key) {
+            keyboard.release(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn finger(finger_id: i64, x: f32, y: f32, down: bool) -> Event {
+        if down {
+            Event::FingerDown {
+                timestamp: 0,
+                touch_id: 0,
+                finger_id,
+                x,
+                y,
+                dx: 0.0,
+                dy: 0.0,
+                pressure: 1.0,
+            }
+        } else {
+            Event::FingerUp {
+                timestamp: 0,
+                touch_id: 0,
+                finger_id,
+                x,
+                y,
+                dx: 0.0,
+                dy: 0.0,
+                pressure: 1.0,
+            }
+        }
+    }
+    #[test]
+    fn should_split_the_window_between_screen_and_keypad() {
+        assert_eq!(window_size(10, Some(TouchKeypad::Beside)), (960, 320));
+        let (screen, keypad) = split((960, 320), TouchKeypad::Beside);
+        assert_eq!(screen, Rect::new(0, 0, 640, 320));
+        assert_eq!(keypad, Rect::new(640, 0, 320, 320));
+        let (screen, keypad) = split((640, 800), TouchKeypad::Under);
+        assert_eq!(screen, Rect::new(0, 0, 640, 400));
+        assert_eq!(keypad, Rect::new(120, 400, 400, 400));
+    }
+    #[test]
+    fn should_find_keys_in_cosmac_vip_order() {
+        let keypad = Rect::new(640, 0, 320, 320);
+        assert_eq!(key_at(keypad, 650, 10), Some(0x1));
+        assert_eq!(key_at(keypad, 959, 10), Some(0xC));
+        assert_eq!(key_at(keypad, 730, 250), Some(0x0));
+        assert_eq!(key_at(keypad, 100, 10), None);
+    }
+    #[test]
+    fn should_hold_chords_and_release_each_finger() {
+        let mut touchpad = Touchpad::new(Some(TouchKeypad::Beside), (960, 320));
+        let mut keyboard = Keyboard::new();
+        // Two fingers on 5 and one on 6
+        touchpad.handle_event(&finger(1, 0.75, 0.375, true), &mut keyboard);
+        touchpad.handle_event(&finger(2, 0.76, 0.375, true), &mut keyboard);
+        touchpad.handle_event(&finger(3, 0.85, 0.375, true), &mut keyboard);
+        assert!(keyboard.is_pressed(0x5) && keyboard.is_pressed(0x6));
+        touchpad.handle_event(&finger(1, 0.75, 0.375, false), &mut keyboard);
+        assert!(keyboard.is_pressed(0x5));
+        touchpad.handle_event(&finger(2, 0.76, 0.375, false), &mut keyboard);
+        assert!(!keyboard.is_pressed(0x5) && keyboard.is_pressed(0x6));
+        // Touches on the screen press nothing
+        touchpad.handle_event(&finger(4, 0.2, 0.5, true), &mut keyboard);
+        touchpad.handle_event(&finger(3, 0.85, 0.375, false), &mut keyboard);
+        assert!(!keyboard.is_any_pressed());
+    }
+}